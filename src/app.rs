@@ -3,6 +3,8 @@ use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
 use crate::sequence::Sequence;
+use crate::plotlines::PLOTLY_JS_PATH;
+use crate::seqplot::SequencePlot;
 
 use plotly::common::{
     Fill, Font, Mode, Title,
@@ -90,6 +92,9 @@ pub fn App() -> impl IntoView {
         // id=leptos means cargo-leptos will hot-reload this stylesheet
         <Stylesheet id="leptos" href="/pkg/seqlines.css"/>
 
+        // plotly.js for the sequence plot, served from `public/`
+        <Script src=PLOTLY_JS_PATH/>
+
         // sets the document title
         <Title text="Welcome to Leptos"/>
 
@@ -115,6 +120,6 @@ pub fn App() -> impl IntoView {
 #[component]
 pub fn HomePage() -> impl IntoView {
     view! {
-        <iframe src="/state/display" title="plotly plots" style="border:none;" width="100%" height="1000"></iframe>
+        <SequencePlot keep_zoom=true/>
     }
 }
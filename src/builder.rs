@@ -0,0 +1,247 @@
+//! Building sequences in Rust without filling the parallel `times` and value
+//! vectors by hand:
+//!
+//! ```
+//! use seqlines::builder::SequenceBuilder;
+//!
+//! let mut builder = SequenceBuilder::new();
+//! builder.analog(42, 0).name("Top Coil").set(0., 0.).ramp(1., 2., 0., 3.);
+//! builder.digital(64, 2).name("MOT shutter").pulse(1.5, 0.2);
+//! builder.dds(50, 0).set(0., 0.5, 80.);
+//! builder.rs485(10, 0).send(0.5, "GO");
+//! let seq = builder.build();
+//! ```
+//!
+//! Points are kept sorted by time, a point at the time of an existing one
+//! going after it, so each vector stays as long as `times` and `times` never
+//! decreases. The server takes the built sequence as a JSON string, i.e.
+//! `serde_json::to_string(&seq.into_json())` as the body of `POST /state`.
+
+use crate::sequence::{AnalogSeq, ChannelKey, ChannelSequence, DDSSeq, DeviceDependentData, DigitalSeq, RS485Seq, Sequence, VCOSeq};
+
+#[derive(Debug, Clone, Default)]
+pub struct SequenceBuilder {
+    channels    : Vec<ChannelSequence>,
+}
+
+/// The channel being built, with typed setters for its kind of data.
+pub struct ChannelBuilder<'a, T> {
+    name    : &'a mut String,
+    data    : &'a mut T,
+}
+
+pub type AnalogChannel<'a> = ChannelBuilder<'a, AnalogSeq>;
+pub type DigitalChannel<'a> = ChannelBuilder<'a, DigitalSeq>;
+pub type DDSChannel<'a> = ChannelBuilder<'a, DDSSeq>;
+pub type VCOChannel<'a> = ChannelBuilder<'a, VCOSeq>;
+pub type RS485Channel<'a> = ChannelBuilder<'a, RS485Seq>;
+
+impl SequenceBuilder {
+    pub fn new() -> Self {
+        SequenceBuilder::default()
+    }
+
+    /// The analog channel `sigchan` of the board at `address`. Asking for a
+    /// channel again continues it; asking for it as another kind of channel
+    /// panics, as a sigchan has one output.
+    pub fn analog(&mut self, address : u8, sigchan : u8) -> AnalogChannel<'_> {
+        self.channel(ChannelKey { address, sigchan },
+            |data| match data { DeviceDependentData::Analog(d) => Some(d), _ => None },
+            || DeviceDependentData::Analog(AnalogSeq { amplitude : vec![], times : vec![] }))
+    }
+
+    pub fn digital(&mut self, address : u8, sigchan : u8) -> DigitalChannel<'_> {
+        self.channel(ChannelKey { address, sigchan },
+            |data| match data { DeviceDependentData::Digital(d) => Some(d), _ => None },
+            || DeviceDependentData::Digital(DigitalSeq { value : vec![], times : vec![] }))
+    }
+
+    pub fn dds(&mut self, address : u8, sigchan : u8) -> DDSChannel<'_> {
+        self.channel(ChannelKey { address, sigchan },
+            |data| match data { DeviceDependentData::DDSRF(d) => Some(d), _ => None },
+            || DeviceDependentData::DDSRF(DDSSeq {
+                amplitude : vec![], frequency : vec![], feature_enable : vec![], feature_value : vec![], times : vec![],
+            }))
+    }
+
+    pub fn vco(&mut self, address : u8, sigchan : u8) -> VCOChannel<'_> {
+        self.channel(ChannelKey { address, sigchan },
+            |data| match data { DeviceDependentData::PLLVCO(d) => Some(d), _ => None },
+            || DeviceDependentData::PLLVCO(VCOSeq { frequency : vec![], times : vec![] }))
+    }
+
+    pub fn rs485(&mut self, address : u8, sigchan : u8) -> RS485Channel<'_> {
+        self.channel(ChannelKey { address, sigchan },
+            |data| match data { DeviceDependentData::RS485(d) => Some(d), _ => None },
+            || DeviceDependentData::RS485(RS485Seq { command : vec![], times : vec![] }))
+    }
+
+    /// The channel at `key`, added with `empty` data if there is none, with
+    /// its data picked by `data_of`. Channels are named `address/sigchan`
+    /// until named otherwise.
+    fn channel<T>(&mut self, key : ChannelKey, data_of : fn(&mut DeviceDependentData) -> Option<&mut T>,
+            empty : fn() -> DeviceDependentData) -> ChannelBuilder<'_, T> {
+        let index = match self.channels.iter().position(|chan| chan.key() == key) {
+            Some(index) => index,
+            None => {
+                self.channels.push(ChannelSequence {
+                    device_dependent : empty(),
+                    name : format!("{}/{}", key.address, key.sigchan),
+                    index_sigchan : key.sigchan,
+                    address : key.address,
+                });
+                self.channels.len() - 1
+            }
+        };
+        let ChannelSequence { name, device_dependent, .. } = &mut self.channels[index];
+        let kind = device_dependent.kind();
+        match data_of(device_dependent) {
+            Some(data) => ChannelBuilder { name, data },
+            None => panic!("channel {}/{} is already of kind {kind:?}, not {:?}", key.address, key.sigchan, empty().kind()),
+        }
+    }
+
+    /// The sequence, with the channels in the order they were first asked
+    /// for. Limits are not checked, see `Sequence::validate`.
+    pub fn build(self) -> Sequence {
+        Sequence { seq_channel : self.channels }
+    }
+}
+
+/// Where a point at `t` goes: after every point at or before `t`.
+fn slot(times : &[f64], t : f64) -> usize {
+    times.partition_point(|&time| time <= t)
+}
+
+impl<T> ChannelBuilder<'_, T> {
+    pub fn name(&mut self, name : impl Into<String>) -> &mut Self {
+        *self.name = name.into();
+        self
+    }
+}
+
+impl AnalogChannel<'_> {
+    /// Outputs `amplitude` from `t` on.
+    pub fn set(&mut self, t : f64, amplitude : f64) -> &mut Self {
+        let i = slot(&self.data.times, t);
+        self.data.times.insert(i, t);
+        self.data.amplitude.insert(i, amplitude);
+        self
+    }
+
+    /// Ramps linearly from `from` at `t0` to `to` at `t1`.
+    pub fn ramp(&mut self, t0 : f64, t1 : f64, from : f64, to : f64) -> &mut Self {
+        self.set(t0, from).set(t1, to)
+    }
+}
+
+impl DigitalChannel<'_> {
+    pub fn set(&mut self, t : f64, high : bool) -> &mut Self {
+        let i = slot(&self.data.times, t);
+        self.data.times.insert(i, t);
+        self.data.value.insert(i, high);
+        self
+    }
+
+    pub fn high(&mut self, t : f64) -> &mut Self {
+        self.set(t, true)
+    }
+
+    pub fn low(&mut self, t : f64) -> &mut Self {
+        self.set(t, false)
+    }
+
+    /// Goes high at `t` and low again `width` later.
+    pub fn pulse(&mut self, t : f64, width : f64) -> &mut Self {
+        self.high(t).low(t + width)
+    }
+}
+
+impl DDSChannel<'_> {
+    /// Outputs `amplitude` at `frequency` from `t` on, with the feature off.
+    pub fn set(&mut self, t : f64, amplitude : f64, frequency : f64) -> &mut Self {
+        self.set_feature(t, amplitude, frequency, None)
+    }
+
+    /// Like `set`, with the device feature enabled at `feature` if given.
+    pub fn set_feature(&mut self, t : f64, amplitude : f64, frequency : f64, feature : Option<f64>) -> &mut Self {
+        let i = slot(&self.data.times, t);
+        self.data.times.insert(i, t);
+        self.data.amplitude.insert(i, amplitude);
+        self.data.frequency.insert(i, frequency);
+        self.data.feature_enable.insert(i, feature.is_some());
+        self.data.feature_value.insert(i, feature.unwrap_or_default());
+        self
+    }
+
+    /// Ramps linearly from `from` at `t0` to `to` at `t1`, both given as
+    /// `(amplitude, frequency)`.
+    pub fn ramp(&mut self, t0 : f64, t1 : f64, from : (f64, f64), to : (f64, f64)) -> &mut Self {
+        self.set(t0, from.0, from.1).set(t1, to.0, to.1)
+    }
+}
+
+impl VCOChannel<'_> {
+    pub fn set(&mut self, t : f64, frequency : f64) -> &mut Self {
+        let i = slot(&self.data.times, t);
+        self.data.times.insert(i, t);
+        self.data.frequency.insert(i, frequency);
+        self
+    }
+
+    pub fn ramp(&mut self, t0 : f64, t1 : f64, from : f64, to : f64) -> &mut Self {
+        self.set(t0, from).set(t1, to)
+    }
+}
+
+impl RS485Channel<'_> {
+    /// Sends `command` at `t`, as its UTF-8 bytes.
+    pub fn send(&mut self, t : f64, command : impl Into<String>) -> &mut Self {
+        let i = slot(&self.data.times, t);
+        self.data.times.insert(i, t);
+        self.data.command.insert(i, command.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_keep_time_order() {
+        let mut builder = SequenceBuilder::new();
+        builder.analog(42, 0).set(2., 20.).set(0., 0.).set(2., 21.).set(1., 10.);
+        let seq = builder.build();
+        let DeviceDependentData::Analog(d) = &seq.seq_channel[0].device_dependent else { panic!() };
+        assert_eq!(d.times, [0., 1., 2., 2.]);
+        assert_eq!(d.amplitude, [0., 10., 20., 21.]);
+    }
+
+    #[test]
+    fn asking_again_continues_the_channel() {
+        let mut builder = SequenceBuilder::new();
+        builder.digital(64, 2).name("shutter").high(1.);
+        builder.digital(64, 2).low(2.);
+        let seq = builder.build();
+        assert_eq!(seq.seq_channel.len(), 1);
+        assert_eq!(seq.seq_channel[0].name, "shutter");
+        assert_eq!(seq.seq_channel[0].device_dependent.times(), [1., 2.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "channel 42/0 is already of kind Analog, not Digital")]
+    fn a_key_has_one_kind() {
+        let mut builder = SequenceBuilder::new();
+        builder.analog(42, 0).set(0., 1.);
+        builder.digital(42, 0).high(1.);
+    }
+
+    #[test]
+    fn commands_go_out_as_utf8_bytes() {
+        let mut builder = SequenceBuilder::new();
+        builder.rs485(10, 0).send(1., "GO").send(0., String::from("µ"));
+        let json = serde_json::to_value(builder.build().seq_channel[0].device_dependent.clone()).unwrap();
+        assert_eq!(json["RS485"]["command"], serde_json::json!([[0xc2, 0xb5], [0x47, 0x4f]]));
+    }
+}
@@ -0,0 +1,284 @@
+//! Offline subcommands of the `seqlines` binary. They work on sequence files
+//! directly, so scripts can produce plots without a running server.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::inventory::{Inventory, InventoryError};
+use crate::rules::{RuleSet, RulesError};
+use crate::export::PLOTLY_JS;
+use crate::plotlines::{DigitalStyle, PlotLayout, PlotlyJs, RenderOptions};
+use crate::registry::{ChannelRegistry, RegistryError};
+use crate::sequence::Sequence;
+use crate::validate::Severity;
+
+pub const USAGE: &str = "\
+usage: seqlines [serve]
+       seqlines render [INPUT] [-o OUTPUT] [--plotly-js URL | --archive] [--max-points N]
+                       [--webgl on|off|auto] [--layout rows|lanes]
+                       [--digital lines|blocks|bars] [--registry FILE]
+                       [--inventory FILE] [--quantize] [--rules FILE]
+       seqlines lint [INPUT] [--format text|json] [--deny-warnings] [--inventory FILE]
+                     [--rules FILE] [--registry FILE]
+
+commands:
+  serve     start the web server (default)
+  render    write the plot of a sequence file as a standalone HTML page
+  lint      check a sequence file, exiting with 1 if it has errors
+
+INPUT is a sequence file, either plain JSON or string-encoded as POSTed to
+/state. It is read from stdin when omitted or `-`. OUTPUT defaults to stdout.
+Rendered pages inline plotly.js unless --plotly-js gives a URL to load it from.
+--archive writes a self-contained page with plotly.js and the sequence inlined.
+--max-points caps the points drawn per trace, 0 draws all of them. --webgl
+forces WebGL drawing; by default dense traces switch to it automatically.
+--layout lanes draws every channel in its own lane instead of one row per
+device kind. --digital draws the high intervals of digital channels as
+blocks or labelled bars instead of step lines. --registry applies the display
+names, colours and order of a channel registry file, as the server does with
+the file named by SEQLINES_REGISTRY. --inventory checks channels against a
+hardware inventory file (lint and --archive) and marks the points breaking
+its device limits, as the server does with the file named by
+SEQLINES_INVENTORY. --quantize draws each channel as its device outputs it,
+snapped to the resolutions and clock tick of the inventory, over the ideal
+trace. --rules checks the cross-channel timing rules of a rules file (lint and
+--archive), as the server does with the file named by SEQLINES_RULES; rules
+may name channels by their display names in the --registry file.";
+
+/// Process exit code of `lint` when the sequence fails a check.
+pub const EXIT_FINDINGS : i32 = 1;
+/// Process exit code for bad arguments and unreadable or malformed input.
+pub const EXIT_USAGE : i32 = 2;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("{0}\n\n{USAGE}")]
+    Usage(String),
+    #[error("cannot read {path}: {source}")]
+    Read { path : String, source : io::Error },
+    #[error("cannot write {path}: {source}")]
+    Write { path : String, source : io::Error },
+    #[error("{path} is not a valid sequence: {source}")]
+    Parse { path : String, source : serde_json::Error },
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error(transparent)]
+    Inventory(#[from] InventoryError),
+    #[error(transparent)]
+    Rules(#[from] RulesError),
+}
+
+/// Runs the offline subcommand named by `args[0]` (program name excluded).
+/// Returns `None` when the server should be started instead, otherwise the
+/// process exit code.
+pub fn run(args : &[String]) -> Option<i32> {
+    let result = match args.first().map(String::as_str) {
+        None | Some("serve") => return None,
+        Some("render") => render(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("-h" | "--help" | "help") => {
+            println!("{USAGE}");
+            Ok(0)
+        }
+        Some(other) => Err(CliError::Usage(format!("unknown command `{other}`"))),
+    };
+    Some(result.unwrap_or_else(|err| {
+        eprintln!("seqlines: {err}");
+        EXIT_USAGE
+    }))
+}
+
+fn render(args : &[String]) -> Result<i32, CliError> {
+    let mut input = None;
+    let mut output = None;
+    let mut plotly_js = PlotlyJs::Inline(PLOTLY_JS);
+    let mut archive = false;
+    let mut opts = RenderOptions::default();
+    let mut rules = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let path = args.next().ok_or_else(|| CliError::Usage(format!("`{arg}` needs a file name")))?;
+                output = Some(path.as_str());
+            }
+            "--plotly-js" => {
+                let url = args.next().ok_or_else(|| CliError::Usage(format!("`{arg}` needs a URL")))?;
+                plotly_js = PlotlyJs::Url(url);
+            }
+            "--archive" => archive = true,
+            "--quantize" => opts.quantize = true,
+            "--max-points" => {
+                opts.max_points = args.next().and_then(|n| n.parse().ok())
+                    .ok_or_else(|| CliError::Usage(format!("`{arg}` needs a number")))?;
+            }
+            "--webgl" => {
+                opts.webgl = match args.next().map(String::as_str) {
+                    Some("on") => Some(true),
+                    Some("off") => Some(false),
+                    Some("auto") => None,
+                    _ => return Err(CliError::Usage(format!("`{arg}` takes `on`, `off` or `auto`"))),
+                };
+            }
+            "--layout" => {
+                opts.layout = match args.next().map(String::as_str) {
+                    Some("rows") => PlotLayout::Rows,
+                    Some("lanes") => PlotLayout::Lanes,
+                    _ => return Err(CliError::Usage(format!("`{arg}` takes `rows` or `lanes`"))),
+                };
+            }
+            "--digital" => {
+                opts.digital = match args.next().map(String::as_str) {
+                    Some("lines") => DigitalStyle::Lines,
+                    Some("blocks") => DigitalStyle::Blocks,
+                    Some("bars") => DigitalStyle::Bars,
+                    _ => return Err(CliError::Usage(format!("`{arg}` takes `lines`, `blocks` or `bars`"))),
+                };
+            }
+            "--registry" => {
+                let path = args.next().ok_or_else(|| CliError::Usage(format!("`{arg}` needs a file name")))?;
+                opts.registry = Some(Arc::new(ChannelRegistry::load(path)?));
+            }
+            "--inventory" => opts.inventory = Some(Arc::new(read_inventory(arg, args.next())?)),
+            "--rules" => rules = Some(read_rules(arg, args.next())?),
+            "-" => input = Some("-"),
+            flag if flag.starts_with('-') => return Err(CliError::Usage(format!("unknown option `{flag}`"))),
+            path if input.is_none() => input = Some(path),
+            extra => return Err(CliError::Usage(format!("unexpected argument `{extra}`"))),
+        }
+    }
+    if rules.is_some() && !archive {
+        return Err(CliError::Usage("`--rules` only applies to `--archive` pages".into()));
+    }
+    let seq = read_sequence(input)?;
+    let inventory = opts.inventory.clone().unwrap_or_default();
+    let rules = RuleSet { registry : opts.registry.clone(), ..rules.unwrap_or_default() };
+    let html = if archive { seq.to_archive_html(&opts, &inventory, &rules) } else { seq.to_html_with(plotly_js, &opts) };
+    write_output(output, &html)?;
+    Ok(0)
+}
+
+fn lint(args : &[String]) -> Result<i32, CliError> {
+    let mut input = None;
+    let mut json = false;
+    let mut deny_warnings = false;
+    let mut inventory = Inventory::default();
+    let mut rules = RuleSet::default();
+    let mut registry = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(String::as_str) {
+                Some("text") => json = false,
+                Some("json") => json = true,
+                _ => return Err(CliError::Usage("`--format` takes `text` or `json`".into())),
+            },
+            "--deny-warnings" => deny_warnings = true,
+            "--inventory" => inventory = read_inventory(arg, args.next())?,
+            "--rules" => rules = read_rules(arg, args.next())?,
+            "--registry" => {
+                let path = args.next().ok_or_else(|| CliError::Usage(format!("`{arg}` needs a file name")))?;
+                registry = Some(Arc::new(ChannelRegistry::load(path)?));
+            }
+            "-" => input = Some("-"),
+            flag if flag.starts_with('-') => return Err(CliError::Usage(format!("unknown option `{flag}`"))),
+            path if input.is_none() => input = Some(path),
+            extra => return Err(CliError::Usage(format!("unexpected argument `{extra}`"))),
+        }
+    }
+    let rules = RuleSet { registry, ..rules };
+    let report = read_sequence(input)?.validate_with(&inventory, &rules);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        for diagnostic in &report.diagnostics {
+            println!("{diagnostic}");
+        }
+        println!("{} error(s), {} warning(s)", report.errors, report.warnings);
+    }
+    let fails = report.diagnostics.iter()
+        .any(|d| d.severity == Severity::Error || deny_warnings);
+    Ok(if fails { EXIT_FINDINGS } else { 0 })
+}
+
+fn read_inventory(arg : &str, path : Option<&String>) -> Result<Inventory, CliError> {
+    let path = path.ok_or_else(|| CliError::Usage(format!("`{arg}` needs a file name")))?;
+    Ok(Inventory::load(path)?)
+}
+
+fn read_rules(arg : &str, path : Option<&String>) -> Result<RuleSet, CliError> {
+    let path = path.ok_or_else(|| CliError::Usage(format!("`{arg}` needs a file name")))?;
+    Ok(RuleSet::load(path)?)
+}
+
+/// Reads and parses a sequence from `path`, or from stdin for `None`/`-`.
+pub fn read_sequence(path : Option<&str>) -> Result<Sequence, CliError> {
+    let (label, content) = match path {
+        None | Some("-") => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)
+                .map_err(|source| CliError::Read { path : "<stdin>".into(), source })?;
+            ("<stdin>".to_string(), content)
+        }
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .map_err(|source| CliError::Read { path : path.into(), source })?;
+            (path.to_string(), content)
+        }
+    };
+    Sequence::from_json(&content).map_err(|source| CliError::Parse { path : label, source })
+}
+
+fn write_output(path : Option<&str>, content : &str) -> Result<(), CliError> {
+    match path {
+        None | Some("-") => io::stdout().write_all(content.as_bytes())
+            .map_err(|source| CliError::Write { path : "<stdout>".into(), source }),
+        Some(path) => fs::write(path, content)
+            .map_err(|source| CliError::Write { path : path.into(), source }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `seqlines lint` on a file holding `sequence`, with `flags`.
+    fn lint_exit(name : &str, sequence : &str, flags : &[&str]) -> i32 {
+        let path = std::env::temp_dir().join(format!("seqlines-lint-{}-{name}.json", std::process::id()));
+        fs::write(&path, sequence).unwrap();
+        let mut args = vec!["lint".to_string(), path.to_string_lossy().into_owned()];
+        args.extend(flags.iter().map(|flag| flag.to_string()));
+        let code = run(&args).unwrap();
+        fs::remove_file(&path).unwrap();
+        code
+    }
+
+    const CLEAN : &str = r#"[{"data": {"Analog": {"amplitude": [0, 1], "times": [0, 1]}}, "name": "coil", "sigchan": 0, "address": 42}]"#;
+    const WARNING : &str = r#"[{"data": {"Analog": {"amplitude": [0, 1], "times": [-1, 1]}}, "name": "coil", "sigchan": 0, "address": 42}]"#;
+    const ERROR : &str = r#"[{"data": {"Analog": {"amplitude": [0, 1], "times": [1, 0]}}, "name": "coil", "sigchan": 0, "address": 42}]"#;
+
+    #[test]
+    fn lint_exits_with_1_on_errors() {
+        assert_eq!(lint_exit("clean", CLEAN, &[]), 0);
+        assert_eq!(lint_exit("warning", WARNING, &[]), 0);
+        assert_eq!(lint_exit("error", ERROR, &[]), EXIT_FINDINGS);
+    }
+
+    #[test]
+    fn lint_exits_with_1_on_warnings_when_denied() {
+        assert_eq!(lint_exit("clean-denied", CLEAN, &["--deny-warnings"]), 0);
+        assert_eq!(lint_exit("warning-denied", WARNING, &["--deny-warnings"]), EXIT_FINDINGS);
+        assert_eq!(lint_exit("error-denied", ERROR, &["--deny-warnings", "--format", "json"]), EXIT_FINDINGS);
+    }
+
+    #[test]
+    fn lint_exits_with_2_on_bad_arguments_and_input() {
+        assert_eq!(lint_exit("flag", CLEAN, &["--deny-errors"]), EXIT_USAGE);
+        assert_eq!(lint_exit("format", CLEAN, &["--format", "xml"]), EXIT_USAGE);
+        assert_eq!(lint_exit("malformed", "[{", &["--deny-warnings"]), EXIT_USAGE);
+        assert_eq!(run(&["lint".into(), "/nonexistent/shot.json".into()]), Some(EXIT_USAGE));
+    }
+}
@@ -0,0 +1,84 @@
+//! Self-contained HTML exports for shot records. The page inlines plotly.js
+//! and embeds the sequence JSON, so it opens without a server or network and
+//! the data can be read back with
+//! `JSON.parse(document.getElementById("seqlines-sequence").textContent)`.
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::inventory::Inventory;
+use crate::rules::RuleSet;
+use crate::plotlines::{escape_script, plot_div, RenderOptions};
+use crate::sequence::{ChannelSequence, Sequence};
+
+/// The plotly.js bundle served from `public/`, inlined into exported pages.
+pub const PLOTLY_JS : &str = include_str!("../public/plotly-2.12.1.min.js");
+/// Id of the `<script type="application/json">` element holding the sequence.
+pub const SEQUENCE_ELEMENT_ID : &str = "seqlines-sequence";
+
+impl Sequence {
+    /// Renders the sequence as an archival page: figure, channel overview,
+    /// check results against `inventory` and `rules` and the sequence JSON,
+    /// with plotly.js inlined.
+    pub fn to_archive_html(&self, opts : &RenderOptions, inventory : &Inventory, rules : &RuleSet) -> String {
+        let exported = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| format_utc(d.as_secs()))
+            .unwrap_or_default();
+        let report = self.validate_with(inventory, rules);
+        let time = |t : Option<f64>| t.map(|t| t.to_string()).unwrap_or_default();
+        let mut rows = String::new();
+        for summary in self.seq_channel.iter().map(ChannelSequence::summary) {
+            let _ = writeln!(rows, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&summary.name), summary.device, summary.address, summary.sigchan,
+                summary.points, time(summary.t_start), time(summary.t_end));
+        }
+        format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    <title>Sequence export {exported}</title>
+    <script>{PLOTLY_JS}</script>
+    <style>
+        body {{ font-family: sans-serif; }}
+        td, th {{ padding: 2px 8px; text-align: left; border-bottom: 1px solid #ccc; }}
+    </style>
+</head>
+<body>
+<h1>Sequence export</h1>
+<p>Exported {exported} by seqlines {version}: {channels} channels, {errors} error(s) and {warnings} warning(s) from the sequence checks.</p>
+{plot}
+<table>
+<tr><th>Name</th><th>Device</th><th>Address</th><th>Sigchan</th><th>Points</th><th>Start</th><th>End</th></tr>
+{rows}</table>
+<script type="application/json" id="{SEQUENCE_ELEMENT_ID}">{json}</script>
+</body>
+</html>
+"#,
+            version = env!("CARGO_PKG_VERSION"),
+            channels = self.seq_channel.len(),
+            errors = report.errors,
+            warnings = report.warnings,
+            plot = plot_div(&self.to_plot(opts).to_json(), "plotly-html-element"),
+            json = escape_script(&self.into_json()),
+        )
+    }
+}
+
+pub fn escape_html(text : &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 UTC time.
+fn format_utc(secs : u64) -> String {
+    // Civil-from-days conversion, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let (era, doe) = (days.div_euclid(146097), days.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let rem = secs % 86400;
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", rem / 3600, rem / 60 % 60, rem % 60)
+}
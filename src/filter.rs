@@ -0,0 +1,79 @@
+//! Selection of the channels to plot, e.g. from the query string of
+//! `/state/display?device=Analog,Digital&address=42&sigchan=0-3&name=Coil`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{de, Deserialize};
+
+use crate::sequence::{ChannelSequence, DeviceKind, Sequence};
+
+/// Channels must match every given criterion; absent criteria match all.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ChannelFilter {
+    /// Comma-separated device kinds, e.g. `Analog,Digital`.
+    #[serde(deserialize_with = "comma_list")]
+    pub device      : Option<Vec<DeviceKind>>,
+    /// Comma-separated board addresses.
+    #[serde(deserialize_with = "comma_list")]
+    pub address     : Option<Vec<u8>>,
+    /// Comma-separated signal channels or inclusive ranges, e.g. `0-3,8`.
+    #[serde(deserialize_with = "comma_list")]
+    pub sigchan     : Option<Vec<SigchanRange>>,
+    /// Case-insensitive part of the channel name.
+    pub name        : Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigchanRange {
+    pub first   : u8,
+    pub last    : u8,
+}
+
+impl FromStr for SigchanRange {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let parse = |n : &str| n.trim().parse::<u8>().map_err(|err| format!("bad sigchan `{n}`: {err}"));
+        match s.split_once('-') {
+            Some((first, last)) => Ok(SigchanRange { first : parse(first)?, last : parse(last)? }),
+            None => parse(s).map(|n| SigchanRange { first : n, last : n }),
+        }
+    }
+}
+
+impl ChannelFilter {
+    pub fn matches(&self, chan : &ChannelSequence) -> bool {
+        let device = self.device.as_ref()
+            .map_or(true, |kinds| kinds.contains(&chan.device_dependent.kind()));
+        let address = self.address.as_ref()
+            .map_or(true, |addresses| addresses.contains(&chan.address));
+        let sigchan = self.sigchan.as_ref()
+            .map_or(true, |ranges| ranges.iter().any(|r| (r.first..=r.last).contains(&chan.index_sigchan)));
+        let name = self.name.as_ref()
+            .map_or(true, |part| chan.name.to_lowercase().contains(&part.to_lowercase()));
+        device && address && sigchan && name
+    }
+}
+
+impl Sequence {
+    /// A copy of the sequence holding only the channels matching `filter`.
+    pub fn filtered(&self, filter : &ChannelFilter) -> Sequence {
+        Sequence { seq_channel : self.seq_channel.iter().filter(|chan| filter.matches(chan)).cloned().collect() }
+    }
+}
+
+fn comma_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let list: Option<String> = de::Deserialize::deserialize(deserializer)?;
+    list.map(|list| list.split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| item.trim().parse().map_err(de::Error::custom))
+            .collect())
+        .transpose()
+}
@@ -0,0 +1,145 @@
+//! The hardware inventory: which board sits at which address, how many
+//! channels it drives and what it can output. Sequences are checked against
+//! it, see `Sequence::validate_with`. It is read from a JSON file like
+//!
+//! ```json
+//! {"boards": [{"address": 42, "device": "Analog", "channels": 8, "name": "Coil drivers",
+//!              "limits": {"amplitude": [-5, 5], "amplitude_resolution": 0.001},
+//!              "channel_limits": {"0": {"max_slew": 0.5, "min_time_step": 1}}},
+//!             {"address": 64, "device": "Digital", "channels": 16, "limits": {"min_high": 0.2}}]}
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::sequence::DeviceKind;
+use crate::validate::DeviceLimits;
+
+pub type InventoryRef = Arc<Inventory>;
+
+/// Environment variable naming the inventory file the server loads.
+pub const INVENTORY_ENV : &str = "SEQLINES_INVENTORY";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Board {
+    pub address     : u8,
+    pub device      : DeviceKind,
+    /// Number of signal channels; valid sigchans are `0..channels`.
+    pub channels    : u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name        : Option<String>,
+    /// Output limits of this board. Absent limits are the built-in ones of
+    /// its device kind, see `DeviceLimits::default_for`.
+    #[serde(default)]
+    pub limits      : DeviceLimits,
+    /// Limits of single channels by sigchan, e.g. the slew rate a coil
+    /// driver can take. Absent limits are those of the board.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub channel_limits : HashMap<u8, DeviceLimits>,
+}
+
+impl Board {
+    /// The limits checked for channel `sigchan` on this board.
+    pub fn limits_for(&self, sigchan : u8) -> DeviceLimits {
+        self.defined_limits_for(sigchan).or(&DeviceLimits::default_for(self.device))
+    }
+
+    /// The limits the inventory sets for channel `sigchan`, without the
+    /// built-in ones.
+    pub fn defined_limits_for(&self, sigchan : u8) -> DeviceLimits {
+        match self.channel_limits.get(&sigchan) {
+            Some(channel) => channel.or(&self.limits),
+            None => self.limits,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Inventory {
+    pub boards  : Vec<Board>,
+}
+
+#[derive(Debug, Error)]
+pub enum InventoryError {
+    #[error("cannot read {path}: {source}")]
+    Read { path : String, source : io::Error },
+    #[error("{path} is not a valid inventory: {source}")]
+    Parse { path : String, source : serde_json::Error },
+    #[error("{path} lists more than one board at address {address}")]
+    DuplicateAddress { path : String, address : u8 },
+}
+
+impl Inventory {
+    pub fn load(path : &str) -> Result<Self, InventoryError> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| InventoryError::Read { path : path.into(), source })?;
+        let inventory : Inventory = serde_json::from_str(&content)
+            .map_err(|source| InventoryError::Parse { path : path.into(), source })?;
+        let mut seen = HashSet::new();
+        if let Some(board) = inventory.boards.iter().find(|board| !seen.insert(board.address)) {
+            return Err(InventoryError::DuplicateAddress { path : path.into(), address : board.address });
+        }
+        Ok(inventory)
+    }
+
+    /// Loads the file named by `SEQLINES_INVENTORY`, or an empty inventory if it is unset.
+    pub fn from_env() -> Result<Self, InventoryError> {
+        match std::env::var(INVENTORY_ENV) {
+            Ok(path) if !path.is_empty() => Inventory::load(&path),
+            _ => Ok(Inventory::default()),
+        }
+    }
+
+    /// An empty inventory checks nothing.
+    pub fn is_empty(&self) -> bool {
+        self.boards.is_empty()
+    }
+
+    pub fn board(&self, address : u8) -> Option<&Board> {
+        self.boards.iter().find(|board| board.address == address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads an inventory from a file holding `json`.
+    fn load(name : &str, json : &str) -> Result<Inventory, InventoryError> {
+        let path = std::env::temp_dir().join(format!("seqlines-inventory-{}-{name}.json", std::process::id()));
+        fs::write(&path, json).unwrap();
+        let inventory = Inventory::load(&path.to_string_lossy());
+        fs::remove_file(&path).unwrap();
+        inventory
+    }
+
+    #[test]
+    fn channel_limits_override_the_board_over_the_built_in_ones() {
+        let inventory = load("limits", r#"{"boards": [{"address": 42, "device": "Analog", "channels": 8,
+            "limits": {"amplitude": [-5, 5], "max_slew": 2},
+            "channel_limits": {"0": {"max_slew": 0.5, "min_time_step": 1}}}]}"#).unwrap();
+        let board = inventory.board(42).unwrap();
+        let channel = board.limits_for(0);
+        assert_eq!((channel.amplitude, channel.max_slew, channel.min_time_step), (Some((-5., 5.)), Some(0.5), Some(1.)));
+        let other = board.limits_for(1);
+        assert_eq!((other.amplitude, other.max_slew, other.min_time_step), (Some((-5., 5.)), Some(2.), None));
+        let built_in = Board { limits : DeviceLimits::default(), ..board.clone() }.limits_for(1);
+        assert_eq!(built_in.amplitude, Some((-10., 10.)));
+        assert_eq!(board.defined_limits_for(1).frequency, None);
+    }
+
+    #[test]
+    fn addresses_hold_one_board() {
+        let boards = r#"{"boards": [{"address": 42, "device": "Analog", "channels": 8},
+            {"address": 16, "device": "Digital", "channels": 32}, {"address": 42, "device": "DDSRF", "channels": 2}]}"#;
+        match load("duplicate", boards) {
+            Err(InventoryError::DuplicateAddress { address, .. }) => assert_eq!(address, 42),
+            other => panic!("expected a duplicate address, got {other:?}"),
+        }
+    }
+}
@@ -5,6 +5,19 @@ pub mod sequence;
 pub mod fileserv;
 pub mod seqserv;    
 pub mod plotlines;
+pub mod validate;
+pub mod filter;
+pub mod window;
+pub mod registry;
+pub mod inventory;
+pub mod quantize;
+pub mod rules;
+pub mod builder;
+pub mod seqplot;
+#[cfg(feature = "ssr")]
+pub mod cli;
+#[cfg(feature = "ssr")]
+pub mod export;
 
 cfg_if! { if #[cfg(feature = "hydrate")] {
     use leptos::*;
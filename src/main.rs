@@ -3,12 +3,18 @@ use std::sync::Arc;
 use leptos::LeptosOptions;
 use seqlines::{app::HomePage, sequence::Sequence};
 use seqlines::seqserv::SequenceRef;
+use seqlines::registry::RegistryRef;
+use seqlines::inventory::InventoryRef;
+use seqlines::rules::RulesRef;
 use axum::{extract::State, response::Html, routing::get, Router};
 
 #[derive(Clone, Debug, axum::extract::FromRef)]
 struct AppState {
     leptos_options : LeptosOptions,
     sequence_ref : SequenceRef,
+    registry : RegistryRef,
+    inventory : InventoryRef,
+    rules : RulesRef,
 }
 
 #[cfg(feature = "ssr")]
@@ -16,12 +22,17 @@ struct AppState {
 async fn main() {
     use std::sync::{Mutex, Arc};
 
-    use axum::{routing::post, routing::get, Router};
+    use axum::{routing::post, routing::get, routing::patch, Router};
     use leptos::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
     use seqlines::app::*;
     use seqlines::fileserv::file_and_error_handler;
-    use seqlines::sequence::Sequence;
+    use seqlines::seqserv::SequenceStore;
+
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = seqlines::cli::run(&args) {
+        std::process::exit(code);
+    }
 
     simple_logger::init_with_level(log::Level::Debug).expect("couldn't initialize logging");
 
@@ -34,17 +45,48 @@ async fn main() {
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
     let routes = generate_route_list(App);
-    let sequence_ref = Arc::new(Mutex::new(Sequence::empty()));
-    let app_state = AppState { leptos_options, sequence_ref };
+    let sequence_ref = Arc::new(Mutex::new(SequenceStore::new()));
+    let registry = match seqlines::registry::ChannelRegistry::from_env() {
+        Ok(registry) => Arc::new(registry),
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(seqlines::cli::EXIT_USAGE);
+        }
+    };
+    let inventory = match seqlines::inventory::Inventory::from_env() {
+        Ok(inventory) => Arc::new(inventory),
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(seqlines::cli::EXIT_USAGE);
+        }
+    };
+    let rules = match seqlines::rules::RuleSet::from_env() {
+        Ok(rules) => Arc::new(seqlines::rules::RuleSet { registry : Some(registry.clone()), ..rules }),
+        Err(err) => {
+            log::error!("{err}");
+            std::process::exit(seqlines::cli::EXIT_USAGE);
+        }
+    };
+    let app_state = AppState { leptos_options, sequence_ref, registry, inventory, rules };
 
     // build our application with a route
     let app = Router::new()
         .route("/state", get(seqlines::seqserv::display_sequence))
         .route("/state", post(seqlines::seqserv::update_sequence))
+        .route("/state", patch(seqlines::seqserv::patch_sequence))
         .route("/state/display", get(seqlines::seqserv::display_plot_content))
+        .route("/state/figure.json", get(seqlines::seqserv::display_figure))
+        .route("/state/lint", get(seqlines::seqserv::lint_sequence))
+        .route("/state/events", get(seqlines::seqserv::sequence_events))
+        .route("/state/export.html", get(seqlines::seqserv::export_sequence))
+        .route("/state/quantization", get(seqlines::seqserv::quantization_errors))
+        .route("/state/channels", get(seqlines::seqserv::list_channels))
+        .route("/state/channels/:address/:sigchan", get(seqlines::seqserv::display_channel))
+        .route("/state/channels/:address/:sigchan/display", get(seqlines::seqserv::display_channel_plot))
         // .route("/", get(get_leptos_component))
+        .route("/devices", get(seqlines::seqserv::list_devices))
         .route("/test", get(test_route))
-        .route("/api/*fn_name", post(leptos_axum::handle_server_fns))
+        .route("/api/*fn_name", post(server_fn_handler))
         .leptos_routes(&app_state, routes, App)
         .fallback(file_and_error_handler)
         .with_state(app_state);
@@ -58,6 +100,23 @@ async fn main() {
         .unwrap();
 }
 
+/// Server functions get the sequence store through the Leptos context.
+async fn server_fn_handler(
+    State(sequence_ref) : State<SequenceRef>,
+    State(registry) : State<RegistryRef>,
+    State(inventory) : State<InventoryRef>,
+    path : axum::extract::Path<String>,
+    headers : http::HeaderMap,
+    raw_query : axum::extract::RawQuery,
+    request : http::Request<axum::body::Body>,
+) -> impl axum::response::IntoResponse {
+    leptos_axum::handle_server_fns_with_context(path, headers, raw_query, move || {
+        leptos::provide_context(sequence_ref.clone());
+        leptos::provide_context(registry.clone());
+        leptos::provide_context(inventory.clone());
+    }, request).await
+}
+
 async fn test_route() -> &'static str {
     "A test on the server."
 }
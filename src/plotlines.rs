@@ -1,16 +1,18 @@
-use std::fmt::Debug;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use crate::sequence::{AnalogSeq, DDSSeq, DeviceDependentData, DigitalSeq, Sequence, VCOSeq};
-
-use leptos::with;
-use plotly::common::{
-    Fill, Font, Label, Line, Mode, PlotType, Title
-};
-use plotly::layout::{
-    self, Axis, AxisConstrain, GridPattern, Layout, LayoutGrid, Margin, RangeSlider, Shape, ShapeLayer, ShapeLine, ShapeType
-};
-use plotly::{Bar, Plot, Scatter, Trace};
-use plotly::color::{NamedColor, Color};
+use crate::filter::ChannelFilter;
+use crate::window::TimeWindow;
+use crate::inventory::InventoryRef;
+use crate::quantize::quantize;
+use crate::registry::{ChannelInfo, RegistryRef};
+use crate::validate::{self, DeviceLimits};
+use crate::sequence::{AnalogSeq, ChannelKey, ChannelSequence, DDSSeq, DeviceDependentData, DigitalSeq, Sequence, VCOSeq};
+
+use plotly::common::{Anchor, AxisSide, DashType, LegendGroupTitle, Line, LineShape, Marker, MarkerSymbol, Mode, Title};
+use plotly::layout::{Annotation, Axis, BarMode, Layout, Margin, RangeSlider, Shape, ShapeLine, ShapeType};
+use plotly::{Plot, Scatter};
+use plotly::color::NamedColor;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, PartialEq, core::cmp::Eq, Hash)]
 pub enum SubplotType {
@@ -24,85 +26,509 @@ pub enum SubplotType {
     DigitalLines,
 }
 
+impl SubplotType {
+    /// All subplot types, in the order their rows are stacked from the bottom.
+    pub const ALL : [SubplotType; 8] = [
+        SubplotType::AnalogAmpl,
+        SubplotType::DDSRFAmpl,
+        SubplotType::DDSRFFreq,
+        SubplotType::PLLVCOFreq,
+        SubplotType::DDSRFShade,
+        SubplotType::DigitalBlocks,
+        SubplotType::DigitalBars,
+        SubplotType::DigitalLines,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            SubplotType::AnalogAmpl => "Analog",
+            SubplotType::DDSRFAmpl => "DDS amplitude",
+            SubplotType::DDSRFFreq => "DDS frequency",
+            SubplotType::PLLVCOFreq => "VCO frequency",
+            SubplotType::DDSRFShade => "DDS",
+            SubplotType::DigitalBlocks | SubplotType::DigitalBars | SubplotType::DigitalLines => "Digital",
+        }
+    }
+
+    /// Height in pixels of a row showing `channels` channels: a base height
+    /// for the first channel and some more for each further one.
+    pub fn row_height(&self, channels : usize) -> f64 {
+        let (base, per_channel) = match self {
+            SubplotType::AnalogAmpl => (200., 40.),
+            SubplotType::DDSRFAmpl | SubplotType::DDSRFFreq | SubplotType::PLLVCOFreq => (150., 25.),
+            SubplotType::DDSRFShade => (60., 10.),
+            SubplotType::DigitalBlocks | SubplotType::DigitalBars | SubplotType::DigitalLines => (60., 30.),
+        };
+        base + per_channel * channels.saturating_sub(1) as f64
+    }
+}
+
 pub type PlotMap<'a> = HashMap<SubplotType, Option<& 'a str>>;
 pub type ScatLine = Box<Scatter<f64, f64>>;
 pub type ScatLines = Vec<Box<Scatter<f64, f64>>>;
 
+/// Where pages served by us load plotly.js from. The file lives in `public/`,
+/// so plots also work on machines without internet access.
+pub const PLOTLY_JS_PATH : &str = "/plotly-2.12.1.min.js";
+
+/// How a page gets plotly.js: from a URL, or inlined as the script itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotlyJs<'a> {
+    Url(&'a str),
+    Inline(&'a str),
+}
+
+impl PlotlyJs<'_> {
+    /// The `<script>` element loading plotly.js.
+    fn script(&self) -> String {
+        match self {
+            PlotlyJs::Url(url) => format!(r#"<script src="{url}"></script>"#),
+            PlotlyJs::Inline(js) => format!("<script>{js}</script>"),
+        }
+    }
+}
+/// Server-Sent Events announcing new sequence revisions to served pages.
+pub const EVENTS_PATH : &str = "/state/events";
+
+/// Points per trace above which traces are decimated by default, enough for
+/// a full-HD wide plot at four points per pixel column.
+pub const DEFAULT_MAX_POINTS : usize = 8000;
+/// Points per trace from which traces are drawn with WebGL by default. SVG
+/// scatter gets sluggish well before the decimation cap.
+pub const DEFAULT_WEBGL_THRESHOLD : usize = 5000;
+
+/// Per-request choices for how a sequence is drawn, e.g. from the query string
+/// of `/state/display?max_points=2000`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RenderOptions {
+    /// Initial x-axis range; the range slider still shows the whole sequence.
+    #[serde(skip)]
+    pub x_range     : Option<(f64, f64)>,
+    /// Longer traces are decimated to about this many points, see `decimate`.
+    /// Zero draws every point.
+    pub max_points  : usize,
+    /// Forces WebGL rendering on or off for all traces. When absent, traces
+    /// with at least `webgl_threshold` points (after decimation) use WebGL.
+    pub webgl       : Option<bool>,
+    pub webgl_threshold : usize,
+    pub layout      : PlotLayout,
+    pub digital     : DigitalStyle,
+    /// Draws each channel as the hardware outputs it over the ideal trace,
+    /// see `quantize`.
+    pub quantize    : bool,
+    /// Display names, order and styling of the channels, see `ChannelRegistry`.
+    #[serde(skip)]
+    pub registry    : Option<RegistryRef>,
+    /// Device limits to mark violations of, see `Inventory`. Without one the
+    /// built-in limits of each device kind are marked.
+    #[serde(skip)]
+    pub inventory   : Option<InventoryRef>,
+}
+
+/// How channels are arranged in the figure, e.g. `?layout=lanes`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotLayout {
+    /// One row per kind of trace, the channels of a kind drawn over each other.
+    #[default]
+    Rows,
+    /// One thin lane per channel, sharing the time axis like a logic analyzer.
+    Lanes,
+}
+
+/// How digital channels are drawn, e.g. `?digital=blocks`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DigitalStyle {
+    /// Step lines, each channel offset by its sigchan.
+    #[default]
+    Lines,
+    /// Filled blocks for the high intervals, one row per channel named on the axis.
+    Blocks,
+    /// Bars for the high intervals with the channel name inside, showing the
+    /// interval on hover.
+    Bars,
+}
+
+impl DigitalStyle {
+    pub fn subplot(&self) -> SubplotType {
+        match self {
+            DigitalStyle::Lines => SubplotType::DigitalLines,
+            DigitalStyle::Blocks => SubplotType::DigitalBlocks,
+            DigitalStyle::Bars => SubplotType::DigitalBars,
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            x_range : None,
+            max_points : DEFAULT_MAX_POINTS,
+            webgl : None,
+            webgl_threshold : DEFAULT_WEBGL_THRESHOLD,
+            layout : PlotLayout::Rows,
+            digital : DigitalStyle::Lines,
+            quantize : false,
+            registry : None,
+            inventory : None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn use_webgl(&self, points : usize) -> bool {
+        self.webgl.unwrap_or(points >= self.webgl_threshold)
+    }
+
+    fn channel_info(&self, key : ChannelKey) -> Option<&ChannelInfo> {
+        self.registry.as_ref().and_then(|registry| registry.info(key))
+    }
+
+    /// Applies the registry entry of channel `key` to its trace: colour,
+    /// legend group, and unit and description on hover.
+    fn style(&self, trace : ScatLine, key : ChannelKey, shape : LineShape) -> ScatLine {
+        let Some(info) = self.channel_info(key) else {
+            return trace;
+        };
+        let mut trace = trace;
+        if let Some(color) = &info.color {
+            trace = trace.line(Line::new().shape(shape).color(color.clone())).marker(Marker::new().color(color.clone()));
+        }
+        if let Some(group) = &info.group {
+            trace = trace.legend_group(group).legend_group_title(LegendGroupTitle::new(group));
+        }
+        if info.unit.is_some() || info.description.is_some() {
+            let unit = info.unit.as_deref().unwrap_or_default();
+            let description = info.description.as_deref().map(|d| format!("<br>{d}")).unwrap_or_default();
+            trace = trace.hover_template(format!("%{{y}} {unit} at %{{x}}{description}"));
+        }
+        trace
+    }
+
+    /// The limits `chan` is drawn against, see `validate::channel_limits`.
+    fn limits(&self, chan : &ChannelSequence) -> DeviceLimits {
+        match &self.inventory {
+            Some(inventory) => validate::channel_limits(chan, inventory),
+            None => DeviceLimits::default_for(chan.device_dependent.kind()),
+        }
+    }
+
+    /// The registered colour of channel `key`, else `fallback`.
+    fn color_or<'a>(&'a self, key : ChannelKey, fallback : &'a str) -> &'a str {
+        self.channel_info(key).and_then(|info| info.color.as_deref()).unwrap_or(fallback)
+    }
+}
+
 impl Sequence {
-    pub fn to_html(&self) -> String {
+    /// Renders the sequence stored as `revision` as a page served by us: it
+    /// loads the server's plotly.js and reloads itself when a newer sequence is stored.
+    pub fn to_html(&self, opts : &RenderOptions, revision : u64) -> String {
+        html_page(&self.to_plot(opts).to_json(), PlotlyJs::Url(PLOTLY_JS_PATH), Some((EVENTS_PATH, revision)))
+    }
+
+    /// Renders the sequence as a standalone page getting plotly.js from `plotly_js`.
+    pub fn to_html_with(&self, plotly_js : PlotlyJs, opts : &RenderOptions) -> String {
+        html_page(&self.to_plot(opts).to_json(), plotly_js, None)
+    }
+
+    /// Plots the channels matching `filter`, cropped to and zoomed on `window`.
+    /// The registry is applied first, so `filter` matches display names.
+    pub fn to_plot_view(&self, filter : &ChannelFilter, window : &TimeWindow, opts : &RenderOptions) -> Figure {
+        let shown = self.registered(opts).filtered(filter);
+        let opts = RenderOptions { x_range : window.x_range(&shown), ..opts.clone() };
+        shown.cropped(window).draw(&opts)
+    }
+
+    /// Draws the sequence, with the names, order and styling of `opts.registry` if set.
+    pub fn to_plot(&self, opts : &RenderOptions) -> Figure {
+        self.registered(opts).draw(opts)
+    }
+
+    /// The sequence with the names and order of `opts.registry`, if set.
+    fn registered(&self, opts : &RenderOptions) -> Cow<'_, Sequence> {
+        match opts.registry.as_ref().filter(|registry| !registry.is_empty()) {
+            Some(registry) => Cow::Owned(registry.apply(self)),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    fn draw(&self, opts : &RenderOptions) -> Figure {
+        let range_slider = RangeSlider::new().visible(true);
+        let x_axis = match opts.x_range {
+            Some((start, end)) => Axis::new().range_slider(range_slider).range(vec![start, end]),
+            None => Axis::new().range_slider(range_slider),
+        };
+        let layout = Layout::new().title(Title::new("Innocent Trial"))
+        .x_axis(x_axis)
+        .plot_background_color(NamedColor::AliceBlue);
+        match opts.layout {
+            PlotLayout::Rows => self.plot_rows(layout, opts),
+            PlotLayout::Lanes => self.plot_lanes(layout, opts),
+        }
+    }
+
+    /// One row per subplot type, see `subplot_rows`.
+    fn plot_rows(&self, layout : Layout, opts : &RenderOptions) -> Figure {
         let mut plot: Plot = Plot::new();
-        let mut plotmap :PlotMap  = HashMap::from([
-            (SubplotType::AnalogAmpl        , Some("y1")),
-            (SubplotType::DDSRFAmpl         , Some("y2")),
-            (SubplotType::DDSRFFreq         , Some("y3")),
-            (SubplotType::PLLVCOFreq        , Some("y4")),
-            (SubplotType::DDSRFShade        , Some("y5")),
-            (SubplotType::DigitalBlocks     , Some("y6")),
-            (SubplotType::DigitalLines      , Some("y7")),            
-        ]);
+        let rows = self.subplot_rows(opts.digital);
+        let axis_names : Vec<String> = (1..=rows.len()).map(Figure::y_axis_id).collect();
+        let mut plotmap : PlotMap = SubplotType::ALL.iter().map(|&kind| (kind, None)).collect();
+        plotmap.extend(rows.iter().zip(&axis_names).map(|(&(kind, _), name)| (kind, Some(name.as_str()))));
         let traces = [ 
-            self.traces_anlg(&plotmap), 
-            self.traces_dds(&plotmap),
-            self.traces_dig(&plotmap),
-            self.traces_vco(&plotmap),
+            self.traces_anlg(&plotmap, opts), 
+            self.traces_dds(&plotmap, opts),
+            self.traces_dig(&plotmap, opts),
+            self.traces_vco(&plotmap, opts),
             ].concat();
         for trace in traces {
             plot.add_trace(trace);
         }
-        let range_slider = RangeSlider::new().visible(true);
-        let layout = Layout::new().title(Title::new("Innocent Trial"))
-        .x_axis(Axis::new().range_slider(range_slider))
-        .plot_background_color(NamedColor::AliceBlue)
-        .height(1000);
-        let layout = adjust_y_height(layout);
+        let digital_keys : Vec<ChannelKey> = self.digital_channels().iter().map(|&(_, _, key)| key).collect();
+        for chan in &self.seq_channel {
+            let levels = match opts.digital {
+                DigitalStyle::Lines => (chan.index_sigchan as f64, chan.index_sigchan as f64 + 1.),
+                _ => {
+                    let row = digital_keys.iter().position(|&key| key == chan.key()).unwrap_or_default() as f64;
+                    (row, row)
+                }
+            };
+            for (field, trace) in [quantized_traces(chan, opts), violation_traces(chan, opts, levels)].concat() {
+                let kind = match (&chan.device_dependent, field) {
+                    (DeviceDependentData::Analog(_), _) => SubplotType::AnalogAmpl,
+                    (DeviceDependentData::DDSRF(_), "amplitude") => SubplotType::DDSRFAmpl,
+                    (DeviceDependentData::DDSRF(_), _) => SubplotType::DDSRFFreq,
+                    (DeviceDependentData::Digital(_), _) => opts.digital.subplot(),
+                    _ => SubplotType::PLLVCOFreq,
+                };
+                plot.add_trace(add_axis(&kind, &plotmap)(trace));
+            }
+        }
+        let row_heights : Vec<(&str, f64)> = rows.iter().map(|(kind, channels)| (kind.title(), kind.row_height(*channels))).collect();
+        let (mut y_axes, height) = stack_y_axes(&row_heights, ROW_GAP);
+        let mut layout = layout.height(height).bar_mode(BarMode::Overlay);
+        let digital_row = rows.iter().position(|&(kind, _)| kind == opts.digital.subplot());
+        if let (Some(row), Some(y), DigitalStyle::Blocks | DigitalStyle::Bars) = (digital_row, plotmap[&opts.digital.subplot()], opts.digital) {
+            let t_end = sequence_end(self);
+            let channels = self.digital_channels();
+            for (i, &(wave, name, key)) in channels.iter().enumerate() {
+                draw_digital_blocks(&mut plot, &mut layout, opts.digital, wave, name, y, i as f64, t_end, opts.color_or(key, digital_color(i)));
+            }
+            let n = channels.len() as f64;
+            y_axes[row] = y_axes[row].clone()
+                .tick_values((0..channels.len()).map(|i| i as f64).collect())
+                .tick_text(channels.iter().map(|(_, name, _)| name.to_string()).collect())
+                .range(vec![-0.6, n - 0.4])
+                .zero_line(false);
+        }
         plot.set_layout(layout);
-        plot.to_html()
+        Figure { plot, y_axes }
+    }
+
+    /// One lane per plotted channel, the first channel on top. Lanes are
+    /// labelled with the channel name and address/sigchan; DDS lanes draw the
+    /// frequency against a second axis on the right.
+    fn plot_lanes(&self, layout : Layout, opts : &RenderOptions) -> Figure {
+        let lanes : Vec<(&ChannelSequence, f64)> = self.seq_channel.iter().rev()
+            .filter_map(|chan| lane_height(&chan.device_dependent).map(|h| (chan, h)))
+            .collect();
+        let rows : Vec<(&str, f64)> = lanes.iter().map(|&(_, h)| ("", h)).collect();
+        let (axes, height) = stack_y_axes(&rows, LANE_GAP);
+        let mut layout = layout.height(height)
+            .bar_mode(BarMode::Overlay)
+            .show_legend(false)
+            .margin(Margin::new().left(LANE_LABEL_WIDTH));
+        let mut figure = Figure { plot : Plot::new(), y_axes : Vec::new() };
+        let t_end = sequence_end(self);
+        for (i, (&(chan, _), axis)) in lanes.iter().zip(axes).enumerate() {
+            let axis = axis.zero_line(false);
+            let y = figure.add_y_axis(match chan.device_dependent {
+                DeviceDependentData::Digital(_) => axis.show_tick_labels(false).range(vec![-0.2, 1.2]),
+                _ => axis,
+            });
+            let key = chan.key();
+            let mut y_freq = None;
+            let traces : ScatLines = match &chan.device_dependent {
+                DeviceDependentData::Analog(d) => vec![opts.style(trace_anlg(d, opts), key, LineShape::Linear).y_axis(&y).name(&chan.name)],
+                DeviceDependentData::Digital(d) if opts.digital != DigitalStyle::Lines => {
+                    draw_digital_blocks(&mut figure.plot, &mut layout, opts.digital, d, &chan.name, &y, 0.5, t_end, opts.color_or(key, digital_color(i)));
+                    vec![]
+                }
+                DeviceDependentData::Digital(d) => vec![opts.style(trace_dig_lines(d, 0, opts), key, LineShape::Hv).y_axis(&y).name(&chan.name)],
+                DeviceDependentData::PLLVCO(d) => vec![opts.style(trace_vco_freq(d, opts), key, LineShape::Linear).y_axis(&y).name(&chan.name)],
+                DeviceDependentData::DDSRF(d) => {
+                    let y_right = figure.add_y_axis(Axis::new().overlaying(&y).side(AxisSide::Right).anchor("x").zero_line(false));
+                    let traces = vec![
+                        opts.style(trace_ddsrf_ampl(d, opts), key, LineShape::Linear).y_axis(&y).name(&chan.name),
+                        opts.style(trace_ddsrf_freq(d, opts), key, LineShape::Linear).y_axis(&y_right).name(&format!("{} frequency", chan.name)),
+                    ];
+                    y_freq = Some(y_right);
+                    traces
+                }
+                _ => vec![],
+            };
+            for trace in traces {
+                figure.plot.add_trace(trace);
+            }
+            let levels = if opts.digital == DigitalStyle::Lines { (0., 1.) } else { (0.5, 0.5) };
+            for (field, trace) in [quantized_traces(chan, opts), violation_traces(chan, opts, levels)].concat() {
+                let axis = match (field, &y_freq) {
+                    ("frequency", Some(y_freq)) => y_freq,
+                    _ => &y,
+                };
+                figure.plot.add_trace(trace.y_axis(axis));
+            }
+            layout.add_annotation(Annotation::new()
+                .text(format!("{}<br>{}/{}", chan.name, chan.address, chan.index_sigchan))
+                .x_ref("paper").x(0.).x_anchor(Anchor::Right).x_shift(-40.)
+                .y_ref(format!("{y} domain")).y(0.5)
+                .show_arrow(false));
+        }
+        figure.plot.set_layout(layout);
+        figure
     }
 
-    pub fn traces_anlg(&self, pm : &PlotMap) -> Vec<Box<Scatter<f64, f64>>> {
-        let info_anlg : Vec<(&AnalogSeq, &String)> = self.seq_channel.iter().filter_map(|seq| 
+    /// The subplot rows needed for the channels present, bottom to top, with
+    /// the number of channels drawn in each.
+    pub fn subplot_rows(&self, digital : DigitalStyle) -> Vec<(SubplotType, usize)> {
+        let mut counts : HashMap<SubplotType, usize> = HashMap::new();
+        for chan in &self.seq_channel {
+            let kinds : &[SubplotType] = match chan.device_dependent {
+                DeviceDependentData::Analog(_) => &[SubplotType::AnalogAmpl],
+                DeviceDependentData::DDSRF(_) => &[SubplotType::DDSRFAmpl, SubplotType::DDSRFFreq],
+                DeviceDependentData::PLLVCO(_) => &[SubplotType::PLLVCOFreq],
+                DeviceDependentData::Digital(_) => &[SubplotType::DigitalLines],
+                _ => &[],
+            };
+            let kinds = kinds.iter().map(|&kind| if kind == SubplotType::DigitalLines { digital.subplot() } else { kind });
+            for kind in kinds {
+                *counts.entry(kind).or_default() += 1;
+            }
+        }
+        SubplotType::ALL.iter().filter_map(|kind| counts.get(kind).map(|&n| (*kind, n))).collect()
+    }
+
+    pub fn traces_anlg(&self, pm : &PlotMap, opts : &RenderOptions) -> Vec<Box<Scatter<f64, f64>>> {
+        let info_anlg : Vec<(&AnalogSeq, &String, ChannelKey)> = self.seq_channel.iter().filter_map(|seq| 
             if let DeviceDependentData::Analog(anlg) = &seq.device_dependent 
-            {Some((anlg, &seq.name))} else {None})
+            {Some((anlg, &seq.name, seq.key()))} else {None})
         .collect::<Vec<_>>();
         let add_y_ampl_axis = add_axis(&SubplotType::AnalogAmpl, pm);
-        info_anlg.iter().map(|&(d, s)| { add_y_ampl_axis(trace_anlg(d).name(s))} ).collect()
+        info_anlg.iter().map(|&(d, s, k)| { add_y_ampl_axis(opts.style(trace_anlg(d, opts), k, LineShape::Linear).name(s))} ).collect()
     }
 
-    pub fn traces_dds(&self, pm : &PlotMap) -> Vec<Box<Scatter<f64, f64>>> {
-        let info_ddsrf : Vec<(&DDSSeq, &String)> = self.seq_channel.iter().filter_map(|seq| 
+    pub fn traces_dds(&self, pm : &PlotMap, opts : &RenderOptions) -> Vec<Box<Scatter<f64, f64>>> {
+        let info_ddsrf : Vec<(&DDSSeq, &String, ChannelKey)> = self.seq_channel.iter().filter_map(|seq| 
             if let DeviceDependentData::DDSRF(ddsrf) = &seq.device_dependent 
-            {Some((ddsrf, &seq.name))} else {None})
+            {Some((ddsrf, &seq.name, seq.key()))} else {None})
         .collect::<Vec<_>>();
         let add_y_ampl_axis = add_axis(&SubplotType::DDSRFAmpl, pm);
         let add_y_freq_axis = add_axis(&SubplotType::DDSRFFreq, pm);
-        let trace_ampl : ScatLines = info_ddsrf.iter().map(|&(d, s)| {
-            add_y_ampl_axis(trace_ddsrf_ampl(d).name(s))})
+        let trace_ampl : ScatLines = info_ddsrf.iter().map(|&(d, s, k)| {
+            add_y_ampl_axis(opts.style(trace_ddsrf_ampl(d, opts), k, LineShape::Linear).name(s))})
             .collect();
-        let trace_freq : ScatLines = info_ddsrf.iter().map(|&(d, s)| {
-            add_y_freq_axis(trace_ddsrf_freq(d).name(s))})
+        let trace_freq : ScatLines = info_ddsrf.iter().map(|&(d, s, k)| {
+            add_y_freq_axis(opts.style(trace_ddsrf_freq(d, opts), k, LineShape::Linear).name(s))})
             .collect();
         [trace_ampl, trace_freq].concat()
     }
 
-    pub fn traces_vco(&self, pm : &PlotMap) -> Vec<Box<Scatter<f64, f64>>> {
-        let info_vco : Vec<(&VCOSeq, &String)> = self.seq_channel.iter().filter_map(|seq| 
+    pub fn traces_vco(&self, pm : &PlotMap, opts : &RenderOptions) -> Vec<Box<Scatter<f64, f64>>> {
+        let info_vco : Vec<(&VCOSeq, &String, ChannelKey)> = self.seq_channel.iter().filter_map(|seq| 
             if let DeviceDependentData::PLLVCO(vco) = &seq.device_dependent 
-            {Some((vco, &seq.name))} else {None})
+            {Some((vco, &seq.name, seq.key()))} else {None})
         .collect::<Vec<_>>();
         let add_y_ampl_axis = add_axis(&SubplotType::PLLVCOFreq, pm);
-        info_vco.iter().map(|&(d, s)| { add_y_ampl_axis(trace_vco_freq(d).name(s))} ).collect()
+        info_vco.iter().map(|&(d, s, k)| { add_y_ampl_axis(opts.style(trace_vco_freq(d, opts), k, LineShape::Linear).name(s))} ).collect()
     }
 
-    pub fn traces_dig(&self, pm : &PlotMap) -> Vec<Box<Scatter<f64, f64>>> {
-        let info_dig : Vec<(&DigitalSeq, &String, u8)> = self.seq_channel.iter().filter_map(|seq| 
-            if let DeviceDependentData::Digital(dig) = &seq.device_dependent 
-            {Some((dig, &seq.name, seq.index_sigchan))} else {None})
-        .collect::<Vec<_>>();
+    /// The digital channels with their names and keys, in sequence order.
+    pub fn digital_channels(&self) -> Vec<(&DigitalSeq, &String, ChannelKey)> {
+        self.seq_channel.iter().filter_map(|seq|
+            if let DeviceDependentData::Digital(dig) = &seq.device_dependent
+            {Some((dig, &seq.name, seq.key()))} else {None})
+        .collect()
+    }
+
+    pub fn traces_dig(&self, pm : &PlotMap, opts : &RenderOptions) -> Vec<Box<Scatter<f64, f64>>> {
+        if opts.digital != DigitalStyle::Lines {
+            return Vec::new();
+        }
         let add_y_ampl_axis = add_axis(&SubplotType::DigitalLines, pm);
-        info_dig.iter().map(|&(d, s, c)| { add_y_ampl_axis(trace_dig_lines(d,c).name(s))} ).collect()
+        self.digital_channels().iter().map(|&(d, s, k)| {
+            add_y_ampl_axis(opts.style(trace_dig_lines(d, k.sigchan, opts), k, LineShape::Hv).name(s))} ).collect()
     }
 }
 
+/// A page drawing the plotly figure `figure_json`. With `events` as the URL
+/// of the revision events and the revision drawn, the page reloads when
+/// another revision is announced, see `live_update_js`.
+pub fn html_page(figure_json : &str, plotly_js : PlotlyJs, events : Option<(&str, u64)>) -> String {
+    let (controls, then) = match events {
+        Some((events, revision)) => (LIVE_CONTROLS, live_update_js(events, revision, "plotly-html-element")),
+        None => ("", String::new()),
+    };
+    format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    {}
+</head>
+<body>
+{controls}{}
+</body>
+</html>
+"#, plotly_js.script(), plot_div_then(figure_json, "plotly-html-element", &then))
+}
+
+/// A `div` with id `div_id` drawing `figure_json`, for pages that already load plotly.js.
+pub fn plot_div(figure_json : &str, div_id : &str) -> String {
+    plot_div_then(figure_json, div_id, "")
+}
+
+/// Like `plot_div`, running `then_js` once the plot is drawn.
+fn plot_div_then(figure_json : &str, div_id : &str, then_js : &str) -> String {
+    format!(r#"<div id="{div_id}" class="plotly-graph-div" style="height:100%; width:100%;"></div>
+<script type="module">
+    await Plotly.newPlot("{div_id}", {});{then_js}
+</script>"#, escape_script(figure_json))
+}
+
+const LIVE_CONTROLS : &str = r#"<label style="font-family:sans-serif; font-size:small;"><input type="checkbox" id="seqlines-keep-zoom"/> Keep zoom on updates</label>
+"#;
+
+/// Reloads the page whenever `events` announces a revision other than
+/// `revision`, the one the page was rendered from. This includes the revision
+/// sent on connect, so a sequence stored while the page loaded is not missed.
+/// If the user ticked "keep zoom", the x-axis range is carried over the
+/// reload in `sessionStorage`.
+fn live_update_js(events : &str, revision : u64, div_id : &str) -> String {
+    format!(r#"
+    const keepZoom = document.getElementById("seqlines-keep-zoom");
+    keepZoom.checked = localStorage.getItem("seqlines-keep-zoom") === "1";
+    keepZoom.onchange = () => localStorage.setItem("seqlines-keep-zoom", keepZoom.checked ? "1" : "0");
+    const savedZoom = sessionStorage.getItem("seqlines-zoom");
+    if (savedZoom) {{
+        sessionStorage.removeItem("seqlines-zoom");
+        await Plotly.relayout("{div_id}", {{"xaxis.range": JSON.parse(savedZoom)}});
+    }}
+    new EventSource("{events}").addEventListener("revision", (event) => {{
+        if (event.data !== "{revision}") {{
+            const range = document.getElementById("{div_id}").layout.xaxis.range;
+            if (keepZoom.checked && range) {{
+                sessionStorage.setItem("seqlines-zoom", JSON.stringify(range));
+            }}
+            location.reload();
+        }}
+    }});"#)
+}
+
+/// Keeps JSON placed inside a `<script>` element from closing it early.
+pub fn escape_script(json : &str) -> String {
+    json.replace("</", "<\\/")
+}
+
 // Should be able to avoid these lifetime annotation nonsense in the next edition of rust 
 pub trait Captures<U> {}
 impl<T: ?Sized, U> Captures<U> for T {}
@@ -117,82 +543,391 @@ pub fn add_axis<'a>(plot_type : &'a SubplotType, map : &'a PlotMap) ->
     }
 }
 
-fn trace_anlg(anlg : &AnalogSeq) -> Box<Scatter<f64, f64>> {
-    Scatter::new(anlg.times.clone(), anlg.amplitude.clone())
+fn trace_anlg(anlg : &AnalogSeq, opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    line_trace(&anlg.times, &anlg.amplitude, opts)
 }
 
-fn trace_ddsrf_ampl(wave : &DDSSeq) -> Box<Scatter<f64, f64>> {
-    Scatter::new(wave.times.clone(), wave.amplitude.clone())
+fn trace_ddsrf_ampl(wave : &DDSSeq, opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    line_trace(&wave.times, &wave.amplitude, opts)
 }
 
-fn trace_ddsrf_freq(wave : &DDSSeq) -> Box<Scatter<f64, f64>> {
-    Scatter::new(wave.times.clone(), wave.frequency.clone())
+fn trace_ddsrf_freq(wave : &DDSSeq, opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    line_trace(&wave.times, &wave.frequency, opts)
 }
 
-fn trace_vco_freq(wave : &VCOSeq) -> Box<Scatter<f64, f64>> {
-    Scatter::new(wave.times.clone(), wave.frequency.clone())
+fn trace_vco_freq(wave : &VCOSeq, opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    line_trace(&wave.times, &wave.frequency, opts)
 }
 
-fn trace_dig_lines(wave : &DigitalSeq, i : u8) -> Box<Scatter<f64, f64>> {
+fn trace_dig_lines(wave : &DigitalSeq, i : u8, opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
     let y = wave.value.clone().iter().map(|v| (i + (if *v {1} else {0})) as f64).collect::<Vec<_>>();
-    Scatter::new(wave.times.clone(), y)
+    step_trace(&wave.times, &y, opts)
         .mode(Mode::LinesMarkers)
-        .line(Line::new().shape(plotly::common::LineShape::Hv))
-}
-
-pub fn adjust_y_height(layout : Layout) -> Layout {
-    let height = &[300.,400.,300.,500.,600.,0.,100.,1600.];
-    let height_tot : f64 = height.iter().sum();
-    let h_gap = 40.;
-    let mut height_cum = [(0., 0.);8];
-    let height_tot = height_cum.iter_mut().enumerate()
-        .fold(0., |cum, (i, h)| {
-            let cum = cum + h_gap;
-            let cum_new = cum + height[i];
-            *h = (cum, cum_new); 
-            cum_new });
-    let domain : Vec<[f64;2]> = height_cum.iter().map(|(b, t)| [b / height_tot, t / height_tot]).collect();
-    println!("Domain Size : {:?}", domain);
-    core::array::from_fn::<_,8,_>(|i| i).iter()
-        .fold(layout, |l, i| {
-        let axis = Axis::new()
-                .domain(&domain[*i])
-                .anchor("x1")
-                .title(Title::new(&i.to_string()));
-        l.new_axis_idx(*i, axis)
-    })    
-}
-
-pub trait AccessAxis {
-    fn new_axis(self, name_axis : Option<&str>, axis : Axis) -> Self;
-    fn new_axis_idx(self, idx_axis : usize, axis : Axis) -> Self;
-}
-
-impl AccessAxis for Layout {
-    fn new_axis(self, name_axis : Option<&str>, axis : Axis) -> Self {
-        match name_axis {
-            Some("y1") => self.y_axis (axis),
-            Some("y2") => self.y_axis2(axis),
-            Some("y3") => self.y_axis3(axis),
-            Some("y4") => self.y_axis4(axis),
-            Some("y5") => self.y_axis5(axis),
-            Some("y6") => self.y_axis6(axis),
-            Some("y7") => self.y_axis7(axis),
-            Some("y8") => self.y_axis8(axis),
-            _ => self
-        }
-    }
-    fn new_axis_idx(self, idx_axis : usize, axis : Axis) -> Self {
-        match idx_axis {
-            1 => self.y_axis (axis),
-            2 => self.y_axis2(axis),
-            3 => self.y_axis3(axis),
-            4 => self.y_axis4(axis),
-            5 => self.y_axis5(axis),
-            6 => self.y_axis6(axis),
-            7 => self.y_axis7(axis),
-            8 => self.y_axis8(axis),
-            _ => self
+        .line(Line::new().shape(LineShape::Hv))
+}
+
+/// With `opts.quantize`, the values of `chan` as its device outputs them,
+/// see `quantize`, one trace per field, named with the worst-case error.
+fn quantized_traces(chan : &ChannelSequence, opts : &RenderOptions) -> Vec<(&'static str, ScatLine)> {
+    if !opts.quantize {
+        return Vec::new();
+    }
+    let limits = opts.limits(chan);
+    let error = chan.quantization_error(&limits);
+    // Unlike plain traces, quantised ones stay capped with `max_points` 0:
+    // a fine clock tick on a long ramp has more steps than can be drawn.
+    let max_points = if opts.max_points == 0 { DEFAULT_MAX_POINTS } else { opts.max_points };
+    let quantized = quantize(&chan.device_dependent, &limits, max_points);
+    let fields : Vec<(&'static str, &[f64], Option<f64>)> = match &quantized {
+        DeviceDependentData::Analog(q) => vec![("amplitude", &q.amplitude, error.amplitude)],
+        DeviceDependentData::PLLVCO(q) => vec![("frequency", &q.frequency, error.frequency)],
+        DeviceDependentData::DDSRF(q) => vec![("amplitude", &q.amplitude, error.amplitude), ("frequency", &q.frequency, error.frequency)],
+        _ => vec![],
+    };
+    // On a clock the output holds each value until the next tick, hence the
+    // steps; without one, ramps are only known to join their points.
+    let shape = if limits.clock_tick.is_some_and(|tick| tick > 0.) { LineShape::Hv } else { LineShape::Linear };
+    let line = Line::new().shape(shape).dash(DashType::Dot).color(opts.color_or(chan.key(), "dimgray").to_string());
+    fields.into_iter().map(|(field, values, max_error)| {
+        let name = format!("{} {field} quantised (error {:.2e}, time {:.2e})", chan.name, max_error.unwrap_or_default(), error.time);
+        (field, line_trace(quantized.times(), values, opts).line(line.clone()).name(name))
+    }).collect()
+}
+
+/// Red crosses on the points of `chan` breaking its device limits, see
+/// `validate::limit_violations`, one trace per field with violations. The
+/// reason shows on hover. Short digital pulses are marked at their start, on
+/// the y of their level in `levels`, given as `(low, high)`.
+fn violation_traces(chan : &ChannelSequence, opts : &RenderOptions, levels : (f64, f64)) -> Vec<(&'static str, ScatLine)> {
+    let mut violations = validate::limit_violations(&chan.device_dependent, &opts.limits(chan));
+    for v in violations.iter_mut().filter(|v| v.field == "pulse") {
+        v.value = levels.0 + v.value * (levels.1 - levels.0);
+    }
+    ["amplitude", "frequency", "pulse"].into_iter().filter_map(|field| {
+        // One marker per point, listing all its violations.
+        let mut points : Vec<(f64, f64, String)> = Vec::new();
+        let mut index : HashMap<(u64, u64), usize> = HashMap::new();
+        for v in violations.iter().filter(|v| v.field == field) {
+            match index.get(&(v.time.to_bits(), v.value.to_bits())) {
+                Some(&i) => points[i].2 = format!("{}<br>{}", points[i].2, v.message),
+                None => {
+                    index.insert((v.time.to_bits(), v.value.to_bits()), points.len());
+                    points.push((v.time, v.value, v.message.clone()));
+                }
+            }
+        }
+        if points.is_empty() {
+            return None;
+        }
+        let webgl = opts.use_webgl(points.len());
+        let trace = Scatter::new(points.iter().map(|p| p.0).collect(), points.iter().map(|p| p.1).collect())
+            .mode(Mode::Markers)
+            .marker(Marker::new().symbol(MarkerSymbol::X).size(9).color(NamedColor::Red))
+            .text_array(points.into_iter().map(|p| p.2).collect())
+            .hover_template("%{text}")
+            .web_gl_mode(webgl)
+            .name(format!("{} {field} limits", chan.name));
+        Some((field, trace))
+    }).collect()
+}
+
+/// Colours of digital blocks and bars, plotly's default colour cycle.
+const DIGITAL_COLORS : [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+    "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+fn digital_color(i : usize) -> &'static str {
+    DIGITAL_COLORS[i % DIGITAL_COLORS.len()]
+}
+
+/// The last time in the sequence, where a level still high at the end of a
+/// digital channel is drawn to.
+fn sequence_end(seq : &Sequence) -> f64 {
+    seq.seq_channel.iter()
+        .flat_map(|chan| chan.device_dependent.times().iter().copied())
+        .fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// The `(start, end)` intervals in which `wave` is high. A level still high
+/// at the last point lasts until `t_end`.
+pub fn high_intervals(wave : &DigitalSeq, t_end : f64) -> Vec<(f64, f64)> {
+    let mut intervals = Vec::new();
+    let mut rise = None;
+    for (&t, &high) in wave.times.iter().zip(&wave.value) {
+        match (rise, high) {
+            (None, true) => rise = Some(t),
+            (Some(start), false) => {
+                intervals.push((start, t));
+                rise = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = rise {
+        intervals.push((start, t_end.max(start)));
+    }
+    intervals
+}
+
+/// Draws the high intervals of `wave` centred on `y` of the axis `y_axis`:
+/// as filled shapes for `DigitalStyle::Blocks`, as a `GanttBars` trace for
+/// `DigitalStyle::Bars`.
+#[allow(clippy::too_many_arguments)]
+fn draw_digital_blocks(plot : &mut Plot, layout : &mut Layout, style : DigitalStyle, wave : &DigitalSeq,
+        name : &str, y_axis : &str, y : f64, t_end : f64, color : &str) {
+    let intervals = high_intervals(wave, t_end);
+    match style {
+        DigitalStyle::Blocks => for (start, end) in intervals {
+            layout.add_shape(Shape::new()
+                .shape_type(ShapeType::Rect)
+                .x_ref("x").y_ref(y_axis)
+                .x0(start).x1(end)
+                .y0(y - 0.4).y1(y + 0.4)
+                .fill_color(color.to_string())
+                .line(ShapeLine::new().width(0.)));
+        },
+        DigitalStyle::Bars => plot.add_trace(Box::new(GanttBars::new(&intervals, name, y_axis, y, color))),
+        DigitalStyle::Lines => {}
+    }
+}
+
+/// Horizontal bars from `base` to `base + x`. plotly's `Bar` has no `base`,
+/// so this serializes the bar trace itself.
+#[derive(Serialize, Clone)]
+struct GanttBars {
+    #[serde(rename = "type")]
+    kind            : &'static str,
+    orientation     : &'static str,
+    name            : String,
+    base            : Vec<f64>,
+    x               : Vec<f64>,
+    y               : Vec<f64>,
+    yaxis           : String,
+    width           : f64,
+    text            : String,
+    textposition    : &'static str,
+    insidetextanchor : &'static str,
+    /// End of each interval, for the hover label.
+    customdata      : Vec<f64>,
+    hovertemplate   : String,
+    marker          : GanttMarker,
+    showlegend      : bool,
+}
+
+#[derive(Serialize, Clone)]
+struct GanttMarker {
+    color   : String,
+}
+
+impl GanttBars {
+    fn new(intervals : &[(f64, f64)], name : &str, y_axis : &str, y : f64, color : &str) -> Self {
+        GanttBars {
+            kind : "bar",
+            orientation : "h",
+            name : name.to_string(),
+            base : intervals.iter().map(|&(start, _)| start).collect(),
+            x : intervals.iter().map(|&(start, end)| end - start).collect(),
+            y : vec![y; intervals.len()],
+            yaxis : y_axis.to_string(),
+            width : 0.8,
+            text : name.to_string(),
+            textposition : "inside",
+            insidetextanchor : "middle",
+            customdata : intervals.iter().map(|&(_, end)| end).collect(),
+            hovertemplate : "%{base} to %{customdata}".to_string(),
+            marker : GanttMarker { color : color.to_string() },
+            showlegend : false,
+        }
+    }
+}
+
+impl plotly::Trace for GanttBars {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// A decimated trace, drawn with WebGL if it is still dense.
+fn line_trace(times : &[f64], values : &[f64], opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    let (x, y) = decimate(times, values, opts.max_points);
+    let webgl = opts.use_webgl(x.len());
+    Scatter::new(x, y).web_gl_mode(webgl)
+}
+
+/// A trace keeping every point, for digital levels where decimation would
+/// drop or move edges; still drawn with WebGL if dense.
+fn step_trace(times : &[f64], values : &[f64], opts : &RenderOptions) -> Box<Scatter<f64, f64>> {
+    let webgl = opts.use_webgl(times.len());
+    Scatter::new(times.to_vec(), values.to_vec()).web_gl_mode(webgl)
+}
+
+/// Reduces a trace to about `max_points` points while keeping its peaks.
+///
+/// The time span is cut into `max_points / 4` buckets, and of the points in
+/// each bucket only the first, the last, the minimum and the maximum are
+/// kept. Glitches shorter than a bucket therefore stay visible, and edges
+/// move by less than a bucket. Traces with at most `max_points` points, and
+/// all traces when `max_points` is zero, are returned unchanged.
+pub fn decimate(times : &[f64], values : &[f64], max_points : usize) -> (Vec<f64>, Vec<f64>) {
+    let len = times.len().min(values.len());
+    let n_buckets = max_points / 4;
+    if max_points == 0 || len <= max_points || n_buckets == 0 {
+        return (times[..len].to_vec(), values[..len].to_vec());
+    }
+    let (t_min, t_max) = (times[0], times[len - 1]);
+    let span = t_max - t_min;
+    let bucket_of = |t : f64| if span > 0. { ((t - t_min) / span * n_buckets as f64) as usize } else { 0 };
+    let mut keep : Vec<usize> = Vec::with_capacity(max_points + 4);
+    let mut start = 0;
+    while start < len {
+        let bucket = bucket_of(times[start]);
+        let end = (start + 1..len).find(|&i| bucket_of(times[i]) != bucket).unwrap_or(len);
+        let by_value = |a : &usize, b : &usize| values[*a].total_cmp(&values[*b]);
+        let min = (start..end).min_by(by_value).unwrap_or(start);
+        let max = (start..end).max_by(by_value).unwrap_or(start);
+        let mut picked = [start, min, max, end - 1];
+        picked.sort_unstable();
+        keep.extend(picked.iter().enumerate().filter(|&(j, i)| j == 0 || picked[j - 1] != *i).map(|(_, &i)| i));
+        start = end;
+    }
+    (keep.iter().map(|&i| times[i]).collect(), keep.iter().map(|&i| values[i]).collect())
+}
+
+/// Pixels left of the plot for the lane labels of the lanes layout.
+const LANE_LABEL_WIDTH : usize = 180;
+/// Gap in pixels between lanes.
+const LANE_GAP : f64 = 6.;
+
+/// Height in pixels of a channel's lane in the lanes layout, `None` for
+/// channels that are not plotted.
+fn lane_height(data : &DeviceDependentData) -> Option<f64> {
+    match data {
+        DeviceDependentData::Digital(_) => Some(25.),
+        DeviceDependentData::Analog(_) | DeviceDependentData::DDSRF(_) | DeviceDependentData::PLLVCO(_) => Some(80.),
+        _ => None,
+    }
+}
+
+/// Gap in pixels between subplot rows.
+const ROW_GAP : f64 = 40.;
+/// Pixels of the figure outside the rows: title, x-axis and range slider.
+const FIGURE_MARGIN : f64 = 250.;
+
+/// Stacks one y-axis per row of `rows`, given as `(title, height in pixels)`
+/// from the bottom up and `gap` pixels apart, and returns the axes with the
+/// figure height fitting them. Empty titles are left out.
+pub fn stack_y_axes(rows : &[(&str, f64)], gap : f64) -> (Vec<Axis>, usize) {
+    let height_tot = rows.iter().map(|(_, h)| h).sum::<f64>() + gap * rows.len().saturating_sub(1) as f64;
+    let mut bottom = 0.;
+    let axes = rows.iter().map(|(title, h)| {
+        let domain = [bottom / height_tot, (bottom + h) / height_tot];
+        bottom += h + gap;
+        let axis = Axis::new().domain(&domain).anchor("x");
+        if title.is_empty() { axis } else { axis.title(Title::new(title)) }
+    }).collect();
+    (axes, (height_tot + FIGURE_MARGIN).round() as usize)
+}
+
+/// A plotly figure with any number of stacked y-axes. plotly's `Layout` only
+/// has fields for `yaxis` to `yaxis8`, so the axes are kept here and merged
+/// into the layout when the figure is serialized.
+pub struct Figure {
+    pub plot    : Plot,
+    /// The axes `y`, `y2`, `y3`, ... in order.
+    pub y_axes  : Vec<Axis>,
+}
+
+impl Figure {
+    /// Id of the `n`th y-axis as traces refer to it, counting from 1: `y`, `y2`, ...
+    pub fn y_axis_id(n : usize) -> String {
+        if n <= 1 { "y".to_string() } else { format!("y{n}") }
+    }
+
+    /// Adds a y-axis and returns its id.
+    pub fn add_y_axis(&mut self, axis : Axis) -> String {
+        self.y_axes.push(axis);
+        Figure::y_axis_id(self.y_axes.len())
+    }
+
+    pub fn to_value(&self) -> serde_json::Value {
+        let mut figure : serde_json::Value = serde_json::from_str(&self.plot.to_json()).unwrap();
+        for (i, axis) in self.y_axes.iter().enumerate() {
+            let key = if i == 0 { "yaxis".to_string() } else { format!("yaxis{}", i + 1) };
+            figure["layout"][key] = serde_json::to_value(axis).unwrap();
         }
+        figure
+    }
+
+    /// The figure as plotly.js takes it, with `data` and `layout`.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimate_leaves_short_traces_alone() {
+        let times = [0., 1., 2.];
+        let values = [3., 4., 5.];
+        assert_eq!(decimate(&times, &values, 8), (times.to_vec(), values.to_vec()));
+        assert_eq!(decimate(&times, &values, 0), (times.to_vec(), values.to_vec()));
+    }
+
+    #[test]
+    fn decimate_keeps_the_extremes_of_each_bucket() {
+        let times : Vec<f64> = (0..1000).map(f64::from).collect();
+        let mut values = vec![0.; 1000];
+        values[500] = 5.;
+        values[501] = -5.;
+        let (x, y) = decimate(&times, &values, 40);
+        assert!(x.len() <= 40, "{} points", x.len());
+        assert_eq!((x[0], x[x.len() - 1]), (0., 999.));
+        assert!(x.windows(2).all(|w| w[0] < w[1]), "times not strictly increasing");
+        assert!(x.iter().zip(&y).any(|p| p == (&500., &5.)));
+        assert!(x.iter().zip(&y).any(|p| p == (&501., &-5.)));
+    }
+
+    #[test]
+    fn decimate_picks_first_min_max_and_last_per_bucket() {
+        let times : Vec<f64> = (0..=20).map(f64::from).collect();
+        let mut values = vec![0.; 21];
+        values[3] = -1.;
+        values[7] = 2.;
+        values[14] = 3.;
+        values[16] = -2.;
+        let (x, y) = decimate(&times, &values, 8);
+        assert_eq!(x, [0., 3., 7., 9., 10., 14., 16., 19., 20.]);
+        assert_eq!(y, [0., -1., 2., 0., 0., 3., -2., 0., 0.]);
+    }
+
+    #[test]
+    fn view_filters_on_registry_names() {
+        use crate::registry::ChannelRegistry;
+        let seq = Sequence { seq_channel : vec![ChannelSequence {
+            device_dependent : DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 1.], times : vec![0., 1.] }),
+            name : "ao0".into(),
+            index_sigchan : 0,
+            address : 42,
+        }] };
+        let registry = ChannelRegistry::from_json(r#"{"channels": [{"address": 42, "sigchan": 0, "name": "Top Coil"}]}"#).unwrap();
+        let opts = RenderOptions { registry : Some(std::sync::Arc::new(registry)), ..RenderOptions::default() };
+        let traces = |name : &str| {
+            let filter = ChannelFilter { name : Some(name.into()), ..ChannelFilter::default() };
+            seq.to_plot_view(&filter, &TimeWindow::default(), &opts).to_value()["data"].as_array().map_or(0, Vec::len)
+        };
+        assert_eq!(traces("top coil"), 1);
+        assert_eq!(traces("ao0"), 0);
+    }
+
+    #[test]
+    fn digital_lines_keep_every_edge() {
+        let wave = DigitalSeq { value : (0..1000).map(|i| i % 2 == 0).collect(), times : (0..1000).map(f64::from).collect() };
+        let opts = RenderOptions { max_points : 40, ..RenderOptions::default() };
+        let trace = serde_json::to_value(&*trace_dig_lines(&wave, 0, &opts)).unwrap();
+        assert_eq!(trace["x"].as_array().unwrap().len(), 1000);
     }
 }
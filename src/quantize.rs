@@ -0,0 +1,318 @@
+//! A preview of what the hardware actually outputs: values snapped to the
+//! output resolution of each device and times to its clock tick, e.g. from
+//! the query string of `/state/display?quantize=true`.
+
+use serde::Serialize;
+
+use crate::inventory::Inventory;
+use crate::sequence::{AnalogSeq, ChannelKey, ChannelSequence, DDSSeq, DeviceDependentData, DigitalSeq, Sequence, VCOSeq};
+use crate::validate::{self, DeviceLimits};
+
+/// `value` rounded to the nearest multiple of `step`; unchanged without a
+/// step or with a step that is not positive.
+pub fn snap(value : f64, step : Option<f64>) -> f64 {
+    match step {
+        Some(step) if step > 0. => (value / step).round() * step,
+        _ => value,
+    }
+}
+
+/// `data` as the device outputs it under `limits`: times on the clock tick,
+/// amplitudes and frequencies on their resolution. With a clock tick, the
+/// device updates its output on the ticks of a ramp, so ramps become
+/// staircases stepping where the snapped value changes; without one, the
+/// points are snapped and ramps stay lines. Steps are thinned out evenly to
+/// keep to about `max_points` points, as a fine tick on a long ramp has far
+/// more steps than can be drawn.
+pub fn quantize(data : &DeviceDependentData, limits : &DeviceLimits, max_points : usize) -> DeviceDependentData {
+    let tick = limits.clock_tick.filter(|&tick| tick > 0.);
+    match data {
+        DeviceDependentData::Analog(d) => {
+            let updates = updates(&d.times, &[(&d.amplitude, limits.amplitude_resolution)], tick, max_points);
+            DeviceDependentData::Analog(AnalogSeq {
+                amplitude : sampled(&updates, &d.amplitude, limits.amplitude_resolution),
+                times : update_times(&updates),
+            })
+        }
+        DeviceDependentData::Digital(d) => DeviceDependentData::Digital(DigitalSeq {
+            value : d.value.clone(),
+            times : d.times.iter().map(|&t| snap(t, tick)).collect(),
+        }),
+        DeviceDependentData::PLLVCO(d) => {
+            let updates = updates(&d.times, &[(&d.frequency, limits.frequency_resolution)], tick, max_points);
+            DeviceDependentData::PLLVCO(VCOSeq {
+                frequency : sampled(&updates, &d.frequency, limits.frequency_resolution),
+                times : update_times(&updates),
+            })
+        }
+        DeviceDependentData::DDSRF(d) => {
+            let fields = [(&d.amplitude[..], limits.amplitude_resolution), (&d.frequency[..], limits.frequency_resolution)];
+            let updates = updates(&d.times, &fields, tick, max_points);
+            DeviceDependentData::DDSRF(DDSSeq {
+                amplitude : sampled(&updates, &d.amplitude, limits.amplitude_resolution),
+                frequency : sampled(&updates, &d.frequency, limits.frequency_resolution),
+                feature_enable : held(&updates, &d.feature_enable),
+                feature_value : held(&updates, &d.feature_value),
+                times : update_times(&updates),
+            })
+        }
+        _ => data.clone(),
+    }
+}
+
+/// The values of one output quantity with their resolution.
+type Field<'a> = (&'a [f64], Option<f64>);
+
+/// The ticks of the ramp from point `i` of `times` to the next at which the
+/// snapped value of `field` may change, as `(count, j -> time)`.
+fn steps(times : &[f64], i : usize, (values, step) : Field, tick : f64) -> (u64, impl Fn(u64) -> f64) {
+    let (t0, t1) = (times[i], times[i + 1]);
+    let (v0, v1) = (values[i], values[i + 1]);
+    let first_tick = (t0 / tick).round() + 1.;
+    let ticks = ((t1 / tick).round() - first_tick).max(0.);
+    // With a resolution coarser than the change per tick, the value only
+    // changes at the ticks after it crosses half a step.
+    let (first_level, levels) = match step.filter(|&step| step > 0.) {
+        Some(step) => {
+            let (lo, hi) = (v0.min(v1) / step - 0.5, v0.max(v1) / step - 0.5);
+            let first = lo.floor() + 1.;
+            (first, (hi.ceil() - first).max(0.))
+        }
+        None => (0., f64::INFINITY),
+    };
+    let by_level = levels < ticks;
+    let count = if by_level { levels } else { ticks } as u64;
+    let rising = v1 > v0;
+    let time = move |j : u64| {
+        if !by_level {
+            return (first_tick + j as f64) * tick;
+        }
+        let level = if rising { first_level + j as f64 } else { first_level + levels - 1. - j as f64 };
+        let value_at = |t : f64| v0 + (t - t0) / (t1 - t0) * (v1 - v0);
+        let crossing = t0 + ((level + 0.5) * step.unwrap_or_default() - v0) / (v1 - v0) * (t1 - t0);
+        // Half steps snap away from zero, so a tick right on the crossing
+        // may still show the old value.
+        let k = (crossing / tick).ceil();
+        let changed = snap(value_at(k * tick), step) != snap(value_at((k - 1.) * tick), step);
+        if changed { k * tick } else { (k + 1.) * tick }
+    };
+    (count, time)
+}
+
+/// The output updates for points at `times`, as `(point, fraction, time)`:
+/// each point at its time on the clock tick, then the ticks before the next
+/// point at which one of `fields` changes its snapped value, `fraction` of
+/// the way there. Beyond `max_points` updates only every so many steps are
+/// kept.
+fn updates(times : &[f64], fields : &[Field], tick : Option<f64>, max_points : usize) -> Vec<(usize, f64, f64)> {
+    let Some(tick) = tick else {
+        return times.iter().enumerate().map(|(i, &t)| (i, 0., t)).collect();
+    };
+    let len = fields.iter().map(|(values, _)| values.len()).fold(times.len(), usize::min);
+    let ramps = |i : usize| (times[i] < times[i + 1]).then(|| fields.iter()
+        .filter(move |(values, _)| values[i] != values[i + 1])
+        .map(move |&field| steps(times, i, field, tick)));
+    let total : u64 = (0..len.saturating_sub(1)).filter_map(ramps).flatten().map(|(count, _)| count).sum();
+    let budget = max_points.saturating_sub(len).max(1) as u64;
+    let stride = total.div_ceil(budget).max(1) as usize;
+    let mut updates = Vec::with_capacity(times.len());
+    for i in 0..len {
+        let t = times[i];
+        let start = snap(t, Some(tick));
+        updates.push((i, 0., start));
+        let Some(ramp) = (i + 1 < len).then(|| ramps(i)).flatten() else { continue };
+        let end = snap(times[i + 1], Some(tick));
+        let mut ticks : Vec<f64> = ramp.flat_map(|(count, time)| (0..count).step_by(stride).map(time).collect::<Vec<_>>())
+            .filter(|&time| start < time && time < end)
+            .collect();
+        ticks.sort_by(f64::total_cmp);
+        ticks.dedup();
+        let dt = times[i + 1] - t;
+        updates.extend(ticks.into_iter().map(|time| (i, ((time - t) / dt).clamp(0., 1.), time)));
+    }
+    updates
+}
+
+fn update_times(updates : &[(usize, f64, f64)]) -> Vec<f64> {
+    updates.iter().map(|&(_, _, t)| t).collect()
+}
+
+/// `values` at each update, interpolated along ramps and snapped to `step`.
+fn sampled(updates : &[(usize, f64, f64)], values : &[f64], step : Option<f64>) -> Vec<f64> {
+    updates.iter().filter_map(|&(i, fraction, _)| {
+        let value = *values.get(i)?;
+        let next = values.get(i + 1).copied().unwrap_or(value);
+        Some(snap(value + fraction * (next - value), step))
+    }).collect()
+}
+
+/// `values` at each update, held from their point.
+fn held<T : Clone>(updates : &[(usize, f64, f64)], values : &[T]) -> Vec<T> {
+    updates.iter().filter_map(|&(i, _, _)| values.get(i).cloned()).collect()
+}
+
+/// The worst-case quantisation error of one channel. Errors are absent for
+/// quantities the channel does not output.
+#[derive(Serialize, Debug, Clone)]
+pub struct QuantizationError {
+    #[serde(flatten)]
+    pub key         : ChannelKey,
+    pub name        : String,
+    pub time        : f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude   : Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency   : Option<f64>,
+}
+
+/// Bound on the difference between the ideal line through `values` and
+/// the output: the snapping of each point to `step`, and on ramps sampled on
+/// the clock `tick`, the rise of the line while a value is held for a tick,
+/// plus half a step for snapping the sample.
+fn max_error(times : &[f64], values : &[f64], step : Option<f64>, tick : Option<f64>) -> f64 {
+    let len = times.len().min(values.len());
+    let at_points = values[..len].iter().map(|&v| (v - snap(v, step)).abs());
+    let half_step = step.filter(|&step| step > 0.).map_or(0., |step| step / 2.);
+    let held = tick.into_iter().flat_map(|tick| (1..len).filter_map(move |i| {
+        let (dt, dv) = (times[i] - times[i - 1], (values[i] - values[i - 1]).abs());
+        (dt > 0. && dv > 0.).then(|| dv.min(dv / dt * tick) + half_step)
+    }));
+    at_points.chain(held).fold(0., f64::max)
+}
+
+/// Largest difference between the times of the points and the same times on
+/// the clock tick.
+fn max_time_error(times : &[f64], tick : Option<f64>) -> f64 {
+    times.iter().map(|&t| (t - snap(t, tick)).abs()).fold(0., f64::max)
+}
+
+impl ChannelSequence {
+    /// How far the output under `limits` strays from the sequence: values
+    /// from the line through its points, times from the times of its points.
+    /// Computed from the points alone, however fine the clock tick.
+    pub fn quantization_error(&self, limits : &DeviceLimits) -> QuantizationError {
+        let tick = limits.clock_tick.filter(|&tick| tick > 0.);
+        let times = self.device_dependent.times();
+        let error = |values : &[f64], step| Some(max_error(times, values, step, tick));
+        let (amplitude, frequency) = match &self.device_dependent {
+            DeviceDependentData::Analog(d) => (error(&d.amplitude, limits.amplitude_resolution), None),
+            DeviceDependentData::PLLVCO(d) => (None, error(&d.frequency, limits.frequency_resolution)),
+            DeviceDependentData::DDSRF(d) => (error(&d.amplitude, limits.amplitude_resolution), error(&d.frequency, limits.frequency_resolution)),
+            _ => (None, None),
+        };
+        QuantizationError {
+            key : self.key(),
+            name : self.name.clone(),
+            time : max_time_error(times, tick),
+            amplitude,
+            frequency,
+        }
+    }
+}
+
+impl Sequence {
+    /// The worst-case quantisation error of every channel with a time axis,
+    /// using the limits of its board in `inventory`.
+    pub fn quantization_errors(&self, inventory : &Inventory) -> Vec<QuantizationError> {
+        self.seq_channel.iter()
+            .filter(|chan| !chan.device_dependent.times().is_empty())
+            .map(|chan| chan.quantization_error(&validate::channel_limits(chan, inventory)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analog(times : Vec<f64>, amplitude : Vec<f64>) -> ChannelSequence {
+        ChannelSequence {
+            device_dependent : DeviceDependentData::Analog(AnalogSeq { amplitude, times }),
+            name : "ramp".into(),
+            index_sigchan : 0,
+            address : 42,
+        }
+    }
+
+    fn limits(clock_tick : Option<f64>, amplitude_resolution : Option<f64>) -> DeviceLimits {
+        DeviceLimits { clock_tick, amplitude_resolution, ..DeviceLimits::default() }
+    }
+
+    #[test]
+    fn snap_rounds_to_the_nearest_step() {
+        assert_eq!(snap(0.26, Some(0.5)), 0.5);
+        assert_eq!(snap(-0.74, Some(0.5)), -0.5);
+        assert_eq!(snap(0.26, None), 0.26);
+        assert_eq!(snap(0.26, Some(0.)), 0.26);
+        assert_eq!(snap(0.26, Some(-1.)), 0.26);
+    }
+
+    #[test]
+    fn quantize_samples_ramps_on_the_clock_tick() {
+        let chan = analog(vec![0., 1.], vec![0., 10.]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(Some(0.25), Some(1.)), 1000) else { panic!() };
+        assert_eq!(q.times, [0., 0.25, 0.5, 0.75, 1.]);
+        assert_eq!(q.amplitude, [0., 3., 5., 8., 10.]);
+    }
+
+    #[test]
+    fn quantize_holds_flat_segments_and_snaps_their_times() {
+        let chan = analog(vec![0.1, 0.9, 0.9], vec![1.2, 1.2, 4.]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(Some(0.25), Some(1.)), 1000) else { panic!() };
+        assert_eq!(q.times, [0., 1., 1.]);
+        assert_eq!(q.amplitude, [1., 1., 4.]);
+    }
+
+    #[test]
+    fn quantize_without_a_clock_snaps_only_the_points() {
+        let chan = analog(vec![0., 1.], vec![0.2, 9.8]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(None, Some(1.)), 1000) else { panic!() };
+        assert_eq!(q.times, [0., 1.]);
+        assert_eq!(q.amplitude, [0., 10.]);
+    }
+
+    #[test]
+    fn quantize_steps_where_the_snapped_value_changes() {
+        // Half steps are crossed at 0.25 and 0.75, on ticks of 1/64.
+        let chan = analog(vec![0., 1.], vec![0., 2.]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(Some(1. / 64.), Some(1.)), 1000) else { panic!() };
+        assert_eq!(q.times, [0., 0.25, 0.75, 1.]);
+        assert_eq!(q.amplitude, [0., 1., 2., 2.]);
+        // Falling, 1.5 and 0.5 still snap to 2 and 1, so steps come a tick later.
+        let chan = analog(vec![0., 1.], vec![2., 0.]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(Some(1. / 64.), Some(1.)), 1000) else { panic!() };
+        assert_eq!(q.times, [0., 0.265625, 0.765625, 1.]);
+        assert_eq!(q.amplitude, [2., 1., 0., 0.]);
+    }
+
+    #[test]
+    fn quantize_caps_the_steps_of_fine_ticks() {
+        let chan = analog(vec![0., 10.], vec![0., 500.]);
+        let DeviceDependentData::Analog(q) = quantize(&chan.device_dependent, &limits(Some(1e-8), None), 100) else { panic!() };
+        assert!(q.times.len() <= 100, "{} points", q.times.len());
+        assert_eq!((q.times[0], q.times[q.times.len() - 1]), (0., 10.));
+        assert!(q.times.windows(2).all(|w| w[0] < w[1]));
+        let error = chan.quantization_error(&limits(Some(1e-8), Some(1e-3)));
+        assert!((error.amplitude.unwrap() - (5e-7 + 5e-4)).abs() < 1e-12, "{:?}", error.amplitude);
+    }
+
+    #[test]
+    fn errors_include_the_steps_of_sampled_ramps() {
+        let chan = analog(vec![0., 1.], vec![0., 10.]);
+        // Exact values, but each is held for a tick while the line rises 2.5.
+        let error = chan.quantization_error(&limits(Some(0.25), None));
+        assert_eq!(error.amplitude, Some(2.5));
+        assert_eq!(error.time, 0.);
+        // Without a clock only the snapped points count.
+        let error = chan.quantization_error(&limits(None, Some(3.)));
+        assert_eq!(error.amplitude, Some(1.));
+    }
+
+    #[test]
+    fn time_error_is_the_largest_shift_to_the_tick() {
+        let chan = analog(vec![0.1, 0.9], vec![0., 0.]);
+        let error = chan.quantization_error(&limits(Some(0.25), None));
+        assert!((error.time - 0.1).abs() < 1e-12, "{}", error.time);
+        assert_eq!(error.amplitude, Some(0.));
+    }
+}
@@ -0,0 +1,127 @@
+//! The channel registry: how channels are presented, keyed by
+//! `(address, sigchan)`, so plots look the same whichever script sent the
+//! sequence. It is read from a JSON file like
+//!
+//! ```json
+//! {"channels": [{"address": 42, "sigchan": 0, "name": "Top Coil", "color": "#1f77b4",
+//!                "group": "Coils", "order": 1, "unit": "A", "description": "Upper MOT coil"}]}
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::sequence::{ChannelKey, ChannelSequence, Sequence};
+
+pub type RegistryRef = Arc<ChannelRegistry>;
+
+/// Environment variable naming the registry file the server loads.
+pub const REGISTRY_ENV : &str = "SEQLINES_REGISTRY";
+
+/// How one channel is presented. Absent fields keep what the sequence says.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ChannelInfo {
+    /// Display name, replacing the name in the sequence.
+    pub name        : Option<String>,
+    /// Trace colour, any CSS colour.
+    pub color       : Option<String>,
+    /// Channels of a group share a legend entry title.
+    pub group       : Option<String>,
+    /// Channels are plotted by ascending order; unordered ones come last.
+    pub order       : Option<i64>,
+    /// Unit of the values, shown on hover.
+    pub unit        : Option<String>,
+    pub description : Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RegistryEntry {
+    #[serde(flatten)]
+    key     : ChannelKey,
+    #[serde(flatten)]
+    info    : ChannelInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct RegistryFile {
+    channels    : Vec<RegistryEntry>,
+}
+
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("cannot read {path}: {source}")]
+    Read { path : String, source : io::Error },
+    #[error("{path} is not a valid channel registry: {source}")]
+    Parse { path : String, source : serde_json::Error },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "RegistryFile", into = "RegistryFile")]
+pub struct ChannelRegistry {
+    channels    : HashMap<ChannelKey, ChannelInfo>,
+}
+
+impl From<RegistryFile> for ChannelRegistry {
+    fn from(file : RegistryFile) -> Self {
+        ChannelRegistry { channels : file.channels.into_iter().map(|entry| (entry.key, entry.info)).collect() }
+    }
+}
+
+impl From<ChannelRegistry> for RegistryFile {
+    fn from(registry : ChannelRegistry) -> Self {
+        let mut channels : Vec<RegistryEntry> = registry.channels.into_iter()
+            .map(|(key, info)| RegistryEntry { key, info })
+            .collect();
+        channels.sort_by_key(|entry| (entry.key.address, entry.key.sigchan));
+        RegistryFile { channels }
+    }
+}
+
+impl ChannelRegistry {
+    pub fn from_json(json : &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn load(path : &str) -> Result<Self, RegistryError> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| RegistryError::Read { path : path.into(), source })?;
+        ChannelRegistry::from_json(&content)
+            .map_err(|source| RegistryError::Parse { path : path.into(), source })
+    }
+
+    /// Loads the file named by `SEQLINES_REGISTRY`, or an empty registry if it is unset.
+    pub fn from_env() -> Result<Self, RegistryError> {
+        match std::env::var(REGISTRY_ENV) {
+            Ok(path) if !path.is_empty() => ChannelRegistry::load(&path),
+            _ => Ok(ChannelRegistry::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn info(&self, key : ChannelKey) -> Option<&ChannelInfo> {
+        self.channels.get(&key)
+    }
+
+    /// A copy of `seq` with the registered display names, sorted by the
+    /// registered order. Channels without an order keep their place after
+    /// the ordered ones.
+    pub fn apply(&self, seq : &Sequence) -> Sequence {
+        let mut channels : Vec<ChannelSequence> = seq.seq_channel.iter().map(|chan| {
+            let mut chan = chan.clone();
+            if let Some(name) = self.info(chan.key()).and_then(|info| info.name.clone()) {
+                chan.name = name;
+            }
+            chan
+        }).collect();
+        channels.sort_by_key(|chan| self.info(chan.key()).and_then(|info| info.order).map_or((1, 0), |order| (0, order)));
+        Sequence { seq_channel : channels }
+    }
+}
@@ -0,0 +1,520 @@
+//! Timing rules spanning channels, checked with the other sequence checks,
+//! see `Sequence::validate_with`. They are read from a JSON file like
+//!
+//! ```json
+//! {"rules": [
+//!   {"name": "shutter closed before imaging", "rule": "before", "min_gap": 5,
+//!    "first": {"channel": "MOT shutter", "edge": "falls"},
+//!    "then": {"channel": "Imaging AOM", "above": 0, "edge": "rises"}},
+//!   {"name": "coils", "rule": "exclusive",
+//!    "conditions": [{"channel": "Top Coil", "above": 3}, {"channel": {"address": 42, "sigchan": 1}, "above": 3}]}
+//! ]}
+//! ```
+//!
+//! Rules are built from conditions on one channel: its value is above and/or
+//! below a bound. A digital channel without bounds is true while high. The
+//! value is the amplitude of analog and DDS channels and the frequency of
+//! VCOs, linear between points as plotted, and held after the last point.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::registry::{ChannelRegistry, RegistryRef};
+use crate::sequence::{ChannelKey, ChannelSequence, DeviceDependentData, Sequence};
+use crate::validate::{Diagnostic, Report};
+
+pub type RulesRef = Arc<RuleSet>;
+
+/// Environment variable naming the rules file the server loads.
+pub const RULES_ENV : &str = "SEQLINES_RULES";
+
+/// A channel, by its name in the sequence or in the registry, or by
+/// `(address, sigchan)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ChannelRef {
+    Key(ChannelKey),
+    Name(String),
+}
+
+/// Why a rule cannot be checked on a sequence.
+#[derive(Debug, Clone)]
+enum Unresolved {
+    Missing(ChannelRef),
+    /// The name fits several channels, given by their keys.
+    Ambiguous(ChannelRef, Vec<ChannelKey>),
+}
+
+impl ChannelRef {
+    /// The one channel of `seq` referred to. A name matches the name of a
+    /// channel in the sequence or its display name in `registry`.
+    fn find<'a>(&self, seq : &'a Sequence, registry : Option<&ChannelRegistry>) -> Result<&'a ChannelSequence, Unresolved> {
+        let found : Vec<&ChannelSequence> = match self {
+            ChannelRef::Key(key) => seq.channel(*key).into_iter().collect(),
+            ChannelRef::Name(name) => seq.seq_channel.iter().filter(|chan| {
+                let display_name = registry.and_then(|registry| registry.info(chan.key())).and_then(|info| info.name.as_ref());
+                &chan.name == name || display_name == Some(name)
+            }).collect(),
+        };
+        match found[..] {
+            [chan] => Ok(chan),
+            [] => Err(Unresolved::Missing(self.clone())),
+            _ => Err(Unresolved::Ambiguous(self.clone(), found.iter().map(|chan| chan.key()).collect())),
+        }
+    }
+}
+
+impl fmt::Display for ChannelRef {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelRef::Key(key) => write!(f, "{}/{}", key.address, key.sigchan),
+            ChannelRef::Name(name) => write!(f, "\"{name}\""),
+        }
+    }
+}
+
+/// True while the value of `channel` lies between the bounds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Condition {
+    pub channel     : ChannelRef,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub above       : Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below       : Option<f64>,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.channel)?;
+        match (self.above, self.below) {
+            (None, None) => write!(f, " high"),
+            (Some(above), None) => write!(f, " above {above}"),
+            (None, Some(below)) => write!(f, " below {below}"),
+            (Some(above), Some(below)) => write!(f, " between {above} and {below}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    /// The condition becomes true.
+    Rises,
+    /// The condition becomes false.
+    Falls,
+    Any,
+}
+
+/// The times a condition becomes true or false.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Event {
+    #[serde(flatten)]
+    pub condition   : Condition,
+    pub edge        : Edge,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let edge = match self.edge {
+            Edge::Rises => "rises",
+            Edge::Falls => "falls",
+            Edge::Any => "changes",
+        };
+        write!(f, "{} {edge}", self.condition)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "rule", rename_all = "lowercase")]
+pub enum Rule {
+    /// Every `then` event comes at least `min_gap` after a `first` event,
+    /// with no opposite change of the `first` condition in between: the
+    /// last change of the condition before `then` must be a `first` event.
+    Before {
+        first       : Event,
+        then        : Event,
+        #[serde(default)]
+        min_gap     : f64,
+    },
+    /// Events `a` and `b` are never closer than `min_gap`.
+    Separation {
+        a           : Event,
+        b           : Event,
+        min_gap     : f64,
+    },
+    /// The conditions are never all true at the same time.
+    Exclusive {
+        conditions  : Vec<Condition>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamedRule {
+    /// Shown in diagnostics; rules without one are numbered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name        : Option<String>,
+    #[serde(flatten)]
+    pub rule        : Rule,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RuleSet {
+    pub rules   : Vec<NamedRule>,
+    /// Display names rules may name channels by, besides their names in the
+    /// sequence.
+    #[serde(skip)]
+    pub registry : Option<RegistryRef>,
+}
+
+#[derive(Debug, Error)]
+pub enum RulesError {
+    #[error("cannot read {path}: {source}")]
+    Read { path : String, source : io::Error },
+    #[error("{path} is not a valid rules file: {source}")]
+    Parse { path : String, source : serde_json::Error },
+}
+
+impl RuleSet {
+    pub fn load(path : &str) -> Result<Self, RulesError> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| RulesError::Read { path : path.into(), source })?;
+        serde_json::from_str(&content)
+            .map_err(|source| RulesError::Parse { path : path.into(), source })
+    }
+
+    /// Loads the file named by `SEQLINES_RULES`, or no rules if it is unset.
+    pub fn from_env() -> Result<Self, RulesError> {
+        match std::env::var(RULES_ENV) {
+            Ok(path) if !path.is_empty() => RuleSet::load(&path),
+            _ => Ok(RuleSet::default()),
+        }
+    }
+
+    /// Checks every rule on `seq`. Rules naming a channel the sequence
+    /// lacks, as sequences may be partial, or a name several channels go by
+    /// are skipped with a warning.
+    pub fn check(&self, seq : &Sequence, report : &mut Report) {
+        let t_end = seq.seq_channel.iter()
+            .flat_map(|chan| chan.device_dependent.times().iter().copied())
+            .fold(f64::NEG_INFINITY, f64::max);
+        let lookup = Lookup { seq, registry : self.registry.as_deref(), t_end };
+        for (i, rule) in self.rules.iter().enumerate() {
+            let label = match &rule.name {
+                Some(name) => format!("rule \"{name}\""),
+                None => format!("rule {}", i + 1),
+            };
+            let message = match rule.rule.check(&lookup, &label, report) {
+                Ok(()) => continue,
+                Err(Unresolved::Missing(channel)) => format!("{label} names {channel}, which is not in the sequence"),
+                Err(Unresolved::Ambiguous(channel, keys)) => {
+                    let keys : Vec<String> = keys.iter().map(|key| format!("{}/{}", key.address, key.sigchan)).collect();
+                    format!("{label} names {channel}, which fits several channels: {}", keys.join(", "))
+                }
+            };
+            report.push(Diagnostic::warning("rule-channel", message));
+        }
+    }
+}
+
+impl Rule {
+    /// Checks the rule on the sequence of `lookup`, or returns the channel
+    /// reference it cannot resolve.
+    fn check(&self, lookup : &Lookup, label : &str, report : &mut Report) -> Result<(), Unresolved> {
+        let fail = |chan : &ChannelSequence, time : f64, message : String| {
+            Diagnostic::error("rule", format!("{label}: {message}")).on(chan).at(time)
+        };
+        match self {
+            Rule::Before { first, then, min_gap } => {
+                let (_, changes) = lookup.changes(&first.condition)?;
+                let (then_chan, then_times) = lookup.event_times(then)?;
+                for t in then_times {
+                    match changes.iter().copied().filter(|&(t_change, _)| t_change <= t).last() {
+                        None => report.push(fail(then_chan, t, format!("{then} with no {first} before it"))),
+                        Some((t_change, edge)) if first.edge != Edge::Any && edge != first.edge => {
+                            let changed = if edge == Edge::Rises { "became true" } else { "became false" };
+                            report.push(fail(then_chan, t, format!("{then} with no {first} since {} {changed} at {t_change}", first.condition)));
+                        }
+                        Some((t_first, _)) if t - t_first < *min_gap => report.push(fail(then_chan, t,
+                            format!("{then} only {} after {first} at {t_first}, needs {min_gap}", t - t_first))),
+                        Some(_) => {}
+                    }
+                }
+            }
+            Rule::Separation { a, b, min_gap } => {
+                let (a_chan, a_times) = lookup.event_times(a)?;
+                let (_, b_times) = lookup.event_times(b)?;
+                for t in a_times {
+                    if let Some(&t_b) = b_times.iter().find(|&&t_b| (t - t_b).abs() < *min_gap) {
+                        report.push(fail(a_chan, t, format!("{a} {} from {b} at {t_b}, needs {min_gap}", (t - t_b).abs())));
+                    }
+                }
+            }
+            Rule::Exclusive { conditions } => {
+                let Some((first, rest)) = conditions.split_first() else { return Ok(()) };
+                let (chan, mut overlap) = lookup.true_intervals(first)?;
+                for condition in rest {
+                    overlap = intersect(&overlap, &lookup.true_intervals(condition)?.1);
+                }
+                let names : Vec<String> = conditions.iter().map(Condition::to_string).collect();
+                for (start, end) in overlap {
+                    report.push(fail(chan, start, format!("{} at once until {end}", names.join(" and "))));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds what rules need of the channels of `seq`, whose last point is at `t_end`.
+struct Lookup<'a> {
+    seq         : &'a Sequence,
+    registry    : Option<&'a ChannelRegistry>,
+    t_end       : f64,
+}
+
+/// A channel found in the sequence with what a rule needs of it, or the
+/// reference that could not be resolved.
+type Found<'a, T> = Result<(&'a ChannelSequence, Vec<T>), Unresolved>;
+
+impl<'a> Lookup<'a> {
+    /// The channel of `condition` with the intervals in which it holds.
+    fn true_intervals(&self, condition : &Condition) -> Found<'a, (f64, f64)> {
+        let chan = condition.channel.find(self.seq, self.registry)?;
+        Ok((chan, true_intervals(&chan.device_dependent, condition, self.t_end).0))
+    }
+
+    /// The channel of `condition` with the times it becomes true (`Rises`)
+    /// or false (`Falls`). The end of an interval cut off by the end of the
+    /// sequence is not a fall.
+    fn changes(&self, condition : &Condition) -> Found<'a, (f64, Edge)> {
+        let chan = condition.channel.find(self.seq, self.registry)?;
+        let (intervals, cut_off) = true_intervals(&chan.device_dependent, condition, self.t_end);
+        let closed = if cut_off { &intervals[..intervals.len() - 1] } else { &intervals[..] };
+        let rises = intervals.iter().map(|&(start, _)| (start, Edge::Rises));
+        let falls = closed.iter().map(|&(_, end)| (end, Edge::Falls));
+        let mut changes : Vec<(f64, Edge)> = rises.chain(falls).collect();
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok((chan, changes))
+    }
+
+    /// The times `event` happens.
+    fn event_times(&self, event : &Event) -> Found<'a, f64> {
+        let (chan, changes) = self.changes(&event.condition)?;
+        let times = changes.into_iter()
+            .filter(|&(_, edge)| event.edge == Edge::Any || edge == event.edge)
+            .map(|(t, _)| t)
+            .collect();
+        Ok((chan, times))
+    }
+}
+
+/// The intervals in which `condition` holds on `data`, sorted and disjoint,
+/// the last value held until `t_end`, and whether the last interval is cut
+/// off there rather than ended by the data. An interval may be empty if it
+/// starts at `t_end`.
+fn true_intervals(data : &DeviceDependentData, condition : &Condition, t_end : f64) -> (Vec<(f64, f64)>, bool) {
+    let (times, values, stepped) : (&[f64], Vec<f64>, bool) = match data {
+        DeviceDependentData::Analog(d) => (&d.times, d.amplitude.clone(), false),
+        DeviceDependentData::DDSRF(d) => (&d.times, d.amplitude.clone(), false),
+        DeviceDependentData::PLLVCO(d) => (&d.times, d.frequency.clone(), false),
+        DeviceDependentData::Digital(d) => (&d.times, d.value.iter().map(|&high| if high { 1. } else { 0. }).collect(), true),
+        _ => (&[], Vec::new(), true),
+    };
+    let (lo, hi) = match (condition.above, condition.below) {
+        (None, None) => (0.5, f64::INFINITY),
+        (above, below) => (above.unwrap_or(f64::NEG_INFINITY), below.unwrap_or(f64::INFINITY)),
+    };
+    let inside = |v : f64| lo < v && v < hi;
+    let mut intervals : Vec<(f64, f64)> = Vec::new();
+    let mut push = |start : f64, end : f64| match intervals.last_mut() {
+        Some(last) if last.1 >= start => last.1 = last.1.max(end),
+        _ => intervals.push((start, end)),
+    };
+    let mut cut_off = false;
+    let len = times.len().min(values.len());
+    for i in 0..len {
+        let (t0, v0) = (times[i], values[i]);
+        if i + 1 == len {
+            // Still a rise when the last point ends the sequence.
+            if inside(v0) {
+                push(t0, t_end.max(t0));
+                cut_off = true;
+            }
+            break;
+        }
+        let (t1, v1) = (times[i + 1], values[i + 1]);
+        if stepped || v0 == v1 {
+            if inside(v0) && t0 < t1 {
+                push(t0, t1);
+            }
+            continue;
+        }
+        // Where the line from (t0, v0) to (t1, v1) lies between the bounds.
+        let (s_lo, s_hi) = ((lo - v0) / (v1 - v0), (hi - v0) / (v1 - v0));
+        let (from, to) = (s_lo.min(s_hi).max(0.), s_lo.max(s_hi).min(1.));
+        if from < to {
+            push(t0 + from * (t1 - t0), t0 + to * (t1 - t0));
+        }
+    }
+    (intervals, cut_off)
+}
+
+/// The intervals covered by both `a` and `b`, each sorted and disjoint.
+fn intersect(a : &[(f64, f64)], b : &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut both = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let (start, end) = (a[i].0.max(b[j].0), a[i].1.min(b[j].1));
+        if start < end {
+            both.push((start, end));
+        }
+        if a[i].1 < b[j].1 { i += 1 } else { j += 1 }
+    }
+    both
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::{AnalogSeq, DigitalSeq};
+
+    fn digital(address : u8, name : &str, times : Vec<f64>, value : Vec<bool>) -> ChannelSequence {
+        ChannelSequence { device_dependent : DeviceDependentData::Digital(DigitalSeq { value, times }), name : name.into(), index_sigchan : 0, address }
+    }
+
+    fn analog(address : u8, name : &str, times : Vec<f64>, amplitude : Vec<f64>) -> ChannelSequence {
+        ChannelSequence { device_dependent : DeviceDependentData::Analog(AnalogSeq { amplitude, times }), name : name.into(), index_sigchan : 0, address }
+    }
+
+    fn rules(json : &str) -> RuleSet {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// The check and time of every diagnostic.
+    fn check(rules : &RuleSet, channels : Vec<ChannelSequence>) -> Vec<(String, Option<f64>)> {
+        let mut report = Report::default();
+        rules.check(&Sequence { seq_channel : channels }, &mut report);
+        report.diagnostics.iter().map(|d| (d.check.to_string(), d.time)).collect()
+    }
+
+    fn shutter_before_imaging() -> RuleSet {
+        rules(r#"{"rules": [{"rule": "before", "min_gap": 5,
+            "first": {"channel": "shutter", "edge": "falls"},
+            "then": {"channel": "imaging", "edge": "rises"}}]}"#)
+    }
+
+    #[test]
+    fn before_needs_the_gap_after_the_first_event() {
+        let shutter = || digital(64, "shutter", vec![0., 10.], vec![true, false]);
+        assert_eq!(check(&shutter_before_imaging(), vec![shutter(), digital(65, "imaging", vec![0., 20.], vec![false, true])]), []);
+        assert_eq!(check(&shutter_before_imaging(), vec![shutter(), digital(65, "imaging", vec![0., 12.], vec![false, true])]),
+            [("rule".to_string(), Some(12.))]);
+    }
+
+    #[test]
+    fn before_fails_when_the_first_condition_changed_back() {
+        // The shutter closes at 10 but opens again at 50, before imaging at 60.
+        let shutter = digital(64, "shutter", vec![0., 10., 50.], vec![true, false, true]);
+        let imaging = digital(65, "imaging", vec![0., 60.], vec![false, true]);
+        assert_eq!(check(&shutter_before_imaging(), vec![shutter, imaging]), [("rule".to_string(), Some(60.))]);
+    }
+
+    #[test]
+    fn before_fails_without_a_first_event() {
+        let shutter = digital(64, "shutter", vec![0.], vec![true]);
+        let imaging = digital(65, "imaging", vec![0., 60.], vec![false, true]);
+        assert_eq!(check(&shutter_before_imaging(), vec![shutter, imaging]), [("rule".to_string(), Some(60.))]);
+    }
+
+    #[test]
+    fn separation_checks_both_sides() {
+        let rule = rules(r#"{"rules": [{"rule": "separation", "min_gap": 1,
+            "a": {"channel": "a", "edge": "any"}, "b": {"channel": "b", "edge": "rises"}}]}"#);
+        let a = digital(64, "a", vec![0., 5., 8.], vec![false, true, false]);
+        let b = digital(65, "b", vec![0., 4.5, 7.], vec![false, true, false]);
+        // The rise of a at 5 is 0.5 after b rises; its fall at 8 is far from any rise of b.
+        assert_eq!(check(&rule, vec![a, b]), [("rule".to_string(), Some(5.))]);
+    }
+
+    #[test]
+    fn falls_at_the_last_time_are_events() {
+        let rule = rules(r#"{"rules": [{"rule": "separation", "min_gap": 1,
+            "a": {"channel": "a", "edge": "any"}, "b": {"channel": "b", "edge": "rises"}}]}"#);
+        let a = digital(64, "a", vec![0., 10.], vec![true, false]);
+        let b = digital(65, "b", vec![0., 9.5], vec![false, true]);
+        assert_eq!(check(&rule, vec![a, b]), [("rule".to_string(), Some(10.))]);
+    }
+
+    #[test]
+    fn intervals_cut_off_by_the_end_do_not_fall() {
+        let rule = rules(r#"{"rules": [{"rule": "separation", "min_gap": 1,
+            "a": {"channel": "a", "edge": "falls"}, "b": {"channel": "b", "edge": "rises"}}]}"#);
+        let a = digital(64, "a", vec![0.], vec![true]);
+        let b = digital(65, "b", vec![0., 10.], vec![false, true]);
+        assert_eq!(check(&rule, vec![a, b]), []);
+    }
+
+    #[test]
+    fn exclusive_reports_overlaps_of_ramps() {
+        let rule = rules(r#"{"rules": [{"rule": "exclusive",
+            "conditions": [{"channel": "top", "above": 3}, {"channel": "bottom", "above": 3}]}]}"#);
+        let top = analog(42, "top", vec![0., 10.], vec![0., 10.]);
+        let bottom = analog(43, "bottom", vec![0., 10.], vec![10., 0.]);
+        // Top is above 3 from 3 on, bottom until 7.
+        assert_eq!(check(&rule, vec![top.clone(), bottom]), [("rule".to_string(), Some(3.))]);
+        let low = analog(43, "bottom", vec![0., 10.], vec![0., 2.]);
+        assert_eq!(check(&rule, vec![top, low]), []);
+    }
+
+    #[test]
+    fn true_intervals_cut_ramps_at_the_bounds() {
+        let condition = Condition { channel : ChannelRef::Name("ramp".into()), above : Some(2.), below : Some(6.) };
+        let ramp = DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 8., 8., 0.], times : vec![0., 8., 10., 18.] });
+        assert_eq!(true_intervals(&ramp, &condition, 20.), (vec![(2., 6.), (12., 16.)], false));
+        // Held after the last point until the end of the sequence.
+        let step = DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 4.], times : vec![0., 2.] });
+        assert_eq!(true_intervals(&step, &condition, 20.), (vec![(1., 20.)], true));
+        assert_eq!(true_intervals(&step, &condition, 2.), (vec![(1., 2.)], true));
+        let late = DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 0., 4.], times : vec![0., 2., 2.] });
+        assert_eq!(true_intervals(&late, &condition, 2.), (vec![(2., 2.)], true));
+    }
+
+    #[test]
+    fn true_intervals_merge_touching_segments() {
+        let condition = Condition { channel : ChannelRef::Name("ramp".into()), above : Some(1.), below : None };
+        let ramp = DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 4., 2., 4.], times : vec![0., 4., 6., 8.] });
+        assert_eq!(true_intervals(&ramp, &condition, 8.), (vec![(1., 8.)], true));
+    }
+
+    #[test]
+    fn intersect_keeps_only_the_overlaps() {
+        let a = [(0., 2.), (3., 6.), (8., 9.)];
+        let b = [(1., 4.), (5., 8.5)];
+        assert_eq!(intersect(&a, &b), [(1., 2.), (3., 4.), (5., 6.), (8., 8.5)]);
+        assert_eq!(intersect(&a, &[]), []);
+    }
+
+    #[test]
+    fn names_resolve_through_the_registry() {
+        let registry = ChannelRegistry::from_json(r#"{"channels": [{"address": 64, "sigchan": 0, "name": "MOT shutter"}]}"#).unwrap();
+        let rule = RuleSet { registry : Some(Arc::new(registry)), ..rules(r#"{"rules": [{"rule": "before", "min_gap": 5,
+            "first": {"channel": "MOT shutter", "edge": "falls"},
+            "then": {"channel": "imaging", "edge": "rises"}}]}"#) };
+        let shutter = digital(64, "ttl0", vec![0., 10.], vec![true, false]);
+        let imaging = digital(65, "imaging", vec![0., 12.], vec![false, true]);
+        assert_eq!(check(&rule, vec![shutter, imaging]), [("rule".to_string(), Some(12.))]);
+    }
+
+    #[test]
+    fn ambiguous_names_skip_the_rule_with_a_warning() {
+        let shutter = || digital(64, "shutter", vec![0., 10.], vec![true, false]);
+        let imaging = digital(65, "imaging", vec![0., 12.], vec![false, true]);
+        let twin = ChannelSequence { index_sigchan : 1, ..shutter() };
+        assert_eq!(check(&shutter_before_imaging(), vec![shutter(), twin, imaging]), [("rule-channel".to_string(), None)]);
+    }
+}
@@ -0,0 +1,131 @@
+//! The plot as a native Leptos component. The sequence is fetched through a
+//! server function and drawn in the browser with the same `plotlines` code
+//! the server uses, so other components can drive it through signals.
+
+use std::sync::Arc;
+
+use leptos::*;
+
+use crate::filter::ChannelFilter;
+use crate::inventory::Inventory;
+use crate::plotlines::RenderOptions;
+use crate::registry::ChannelRegistry;
+use crate::sequence::Sequence;
+use crate::window::TimeWindow;
+
+/// Id of the div the component draws into.
+pub const PLOT_DIV_ID : &str = "seqlines-plot";
+
+/// The stored sequence, for clients of the Leptos app.
+#[server(GetSequence, "/api")]
+pub async fn get_sequence() -> Result<Sequence, ServerFnError> {
+    use crate::seqserv::SequenceRef;
+
+    let store = use_context::<SequenceRef>()
+        .ok_or_else(|| ServerFnError::ServerError("sequence store missing from context".into()))?;
+    let seq = store.lock().unwrap().sequence();
+    Ok(Sequence::clone(&seq))
+}
+
+/// The channel registry the server plots with.
+#[server(GetRegistry, "/api")]
+pub async fn get_registry() -> Result<ChannelRegistry, ServerFnError> {
+    use crate::registry::RegistryRef;
+
+    let registry = use_context::<RegistryRef>()
+        .ok_or_else(|| ServerFnError::ServerError("channel registry missing from context".into()))?;
+    Ok(ChannelRegistry::clone(&registry))
+}
+
+/// The hardware inventory whose device limits the plot marks.
+#[server(GetDevices, "/api")]
+pub async fn get_devices() -> Result<Inventory, ServerFnError> {
+    use crate::inventory::InventoryRef;
+
+    let inventory = use_context::<InventoryRef>()
+        .ok_or_else(|| ServerFnError::ServerError("hardware inventory missing from context".into()))?;
+    Ok(Inventory::clone(&inventory))
+}
+
+/// Plots the stored sequence and redraws whenever a new one is stored or a
+/// prop changes.
+#[component]
+pub fn SequencePlot(
+    /// Channels to show, all by default.
+    #[prop(into, optional)]
+    filter : MaybeSignal<ChannelFilter>,
+    /// Time window to crop to, the whole sequence by default.
+    #[prop(into, optional)]
+    window : MaybeSignal<TimeWindow>,
+    #[prop(into, optional)]
+    options : MaybeSignal<RenderOptions>,
+    /// Keeps the user's zoom when the sequence is redrawn.
+    #[prop(into, optional)]
+    keep_zoom : MaybeSignal<bool>,
+) -> impl IntoView {
+    let (revision, set_revision) = create_signal(0u64);
+    let sequence = create_local_resource(move || revision.get(), |_| get_sequence());
+    let registry = create_local_resource(|| (), |_| get_registry());
+    let inventory = create_local_resource(|| (), |_| get_devices());
+    listen_for_revisions(set_revision);
+
+    create_effect(move |_| {
+        let seq = match sequence.get() {
+            Some(Ok(seq)) => seq,
+            Some(Err(err)) => return log::error!("Cannot load the sequence: {err}"),
+            None => return,
+        };
+        let opts = RenderOptions {
+            registry : registry.get().and_then(Result::ok).map(Arc::new),
+            inventory : inventory.get().and_then(Result::ok).map(Arc::new),
+            ..options.get()
+        };
+        let mut figure = seq.to_plot_view(&filter.get(), &window.get(), &opts).to_value();
+        // plotly.js keeps the zoom across redraws while `uirevision` stays the same.
+        figure["layout"]["uirevision"] = if keep_zoom.get() { "seqlines".into() } else { revision.get_untracked().into() };
+        redraw(PLOT_DIV_ID, &figure.to_string());
+    });
+
+    view! {
+        <div id=PLOT_DIV_ID class="plotly-graph-div" style="height:100%; width:100%;"></div>
+    }
+}
+
+cfg_if::cfg_if! { if #[cfg(feature = "hydrate")] {
+    use wasm_bindgen::{prelude::*, JsCast};
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(catch, js_namespace = Plotly, js_name = react)]
+        fn plotly_react(div_id : &str, figure : &JsValue) -> Result<js_sys::Promise, JsValue>;
+    }
+
+    fn redraw(div_id : &str, figure_json : &str) {
+        let drawn = js_sys::JSON::parse(figure_json).and_then(|figure| plotly_react(div_id, &figure));
+        if let Err(err) = drawn {
+            log::error!("Cannot draw the sequence: {err:?}");
+        }
+    }
+
+    /// Feeds the revisions announced at `/state/events` into `set_revision`.
+    fn listen_for_revisions(set_revision : WriteSignal<u64>) {
+        let Ok(source) = web_sys::EventSource::new(crate::plotlines::EVENTS_PATH) else {
+            return log::error!("Cannot subscribe to sequence updates");
+        };
+        let on_revision = Closure::<dyn Fn(web_sys::MessageEvent)>::new(move |event : web_sys::MessageEvent| {
+            if let Some(revision) = event.data().as_string().and_then(|data| data.parse().ok()) {
+                set_revision.set(revision);
+            }
+        });
+        let _ = source.add_event_listener_with_callback("revision", on_revision.as_ref().unchecked_ref());
+        on_cleanup(move || {
+            source.close();
+            drop(on_revision);
+        });
+    }
+} else {
+    // Drawing happens in the browser only.
+    fn redraw(_div_id : &str, _figure_json : &str) {}
+
+    fn listen_for_revisions(_set_revision : WriteSignal<u64>) {}
+}}
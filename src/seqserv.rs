@@ -1,41 +1,303 @@
-use std::{fs::File, io::{Write, Read, BufReader}};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use cfg_if::cfg_if;
 
-use crate::sequence::Sequence;    
+use crate::sequence::Sequence;
 
-pub type SequenceRef = Arc<Mutex<Sequence>>;
+pub type SequenceRef = Arc<Mutex<SequenceStore>>;
+
+/// Pages kept per revision before the cache starts over.
+const MAX_CACHED_PAGES : usize = 32;
+
+/// The current sequence, the revision counting how often it was stored, and
+/// the plot pages rendered from this revision, keyed by route and query string.
+///
+/// Readers take a snapshot of the sequence and render without holding the lock.
+#[derive(Debug)]
+pub struct SequenceStore {
+    sequence    : Arc<Sequence>,
+    revision    : u64,
+    pages       : HashMap<String, Arc<String>>,
+    /// Announces every new revision to `/state/events` listeners.
+    #[cfg(feature = "ssr")]
+    revisions   : tokio::sync::watch::Sender<u64>,
+}
+
+impl Default for SequenceStore {
+    fn default() -> Self {
+        SequenceStore {
+            sequence : Arc::default(),
+            revision : 0,
+            pages : HashMap::new(),
+            #[cfg(feature = "ssr")]
+            revisions : tokio::sync::watch::channel(0).0,
+        }
+    }
+}
+
+impl SequenceStore {
+    pub fn new() -> Self {
+        SequenceStore::default()
+    }
+    pub fn sequence(&self) -> Arc<Sequence> {
+        self.sequence.clone()
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    /// Stores a new sequence, invalidating the rendered pages.
+    pub fn replace(&mut self, seq : Sequence) {
+        self.sequence = Arc::new(seq);
+        self.bump();
+    }
+    /// Changes the sequence in place, invalidating the rendered pages.
+    pub fn modify<R>(&mut self, change : impl FnOnce(&mut Sequence) -> R) -> R {
+        let result = change(Arc::make_mut(&mut self.sequence));
+        self.bump();
+        result
+    }
+    fn bump(&mut self) {
+        self.revision += 1;
+        self.pages.clear();
+        #[cfg(feature = "ssr")]
+        self.revisions.send_replace(self.revision);
+    }
+    #[cfg(feature = "ssr")]
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<u64> {
+        self.revisions.subscribe()
+    }
+    pub fn cached_page(&self, key : &str) -> Option<Arc<String>> {
+        self.pages.get(key).cloned()
+    }
+    /// Keeps a page rendered from `revision`, unless the sequence changed meanwhile.
+    pub fn cache_page(&mut self, revision : u64, key : String, page : Arc<String>) {
+        if revision == self.revision {
+            if self.pages.len() >= MAX_CACHED_PAGES {
+                self.pages.clear();
+            }
+            self.pages.insert(key, page);
+        }
+    }
+}
 
 cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::convert::Infallible;
+    use std::sync::OnceLock;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
     use axum::{
-        body::{boxed, Body, BoxBody},
-        extract::State,
+        extract::{Path, Query, RawQuery, State},
         response::IntoResponse,
-        http::{Request, StatusCode, Uri},
+        response::sse::{Event, KeepAlive, Sse},
+        http::{header, HeaderMap, StatusCode},
     };
+    use futures::Stream;
+    use serde::Deserialize;
 
-    use axum::response::Response as AxumResponse;
-    use tower::ServiceExt;
-    use tower_http::services::ServeDir;
-    use leptos::*;
+    use crate::filter::ChannelFilter;
+    use crate::plotlines::{html_page, plot_div, PlotlyJs, RenderOptions, EVENTS_PATH, PLOTLY_JS_PATH};
+    use crate::inventory::{Inventory, InventoryRef};
+    use crate::quantize::QuantizationError;
+    use crate::registry::RegistryRef;
+    use crate::rules::RulesRef;
+    use crate::window::TimeWindow;
+    use crate::sequence::{ChannelKey, SequencePatch};
 
-    pub async fn update_sequence(uri: Uri, State(seq): State<SequenceRef>, axum::Json(new_seq) : axum::Json<String>) -> axum::response::Response {
+    pub async fn update_sequence(State(seq): State<SequenceRef>, State(inventory): State<InventoryRef>, State(rules): State<RulesRef>, axum::Json(new_seq) : axum::Json<String>) -> axum::response::Response {
         // let mut file = File::create("test.json").unwrap();
         // file.write_all(new_seq.as_bytes()).unwrap();
-        println!("Updating content: {}", &new_seq);
-        (*seq.clone().lock().unwrap()).update_from_json(&new_seq);
+        let new_seq = match Sequence::from_json(&new_seq) {
+            Ok(new_seq) => new_seq,
+            Err(err) => return (StatusCode::BAD_REQUEST, format!("Invalid sequence: {err}")).into_response(),
+        };
+        // Bad sequences are still stored so they can be inspected on the plot.
+        let report = new_seq.validate_with(&inventory, &rules);
+        for diagnostic in &report.diagnostics {
+            log::warn!("{diagnostic}");
+        }
+        let channels = new_seq.seq_channel.len();
+        let mut store = seq.lock().unwrap();
+        store.replace(new_seq);
+        log::info!("Stored sequence revision {} with {channels} channel(s)", store.revision());
         "Hey! I got it!".into_response()
     }
 
-    pub async fn display_sequence(uri: Uri, State(seq): State<SequenceRef>, req: Request<Body>) -> axum::response::Response {
-        let seq_inner = seq.lock().unwrap();
-        seq_inner.into_json().into_response()
+    /// Replaces, adds or removes single channels, see `SequencePatch`.
+    pub async fn patch_sequence(State(seq): State<SequenceRef>, State(inventory): State<InventoryRef>, State(rules): State<RulesRef>, body : String) -> axum::response::Response {
+        let patch = match SequencePatch::from_json(&body) {
+            Ok(patch) => patch,
+            Err(err) => return (StatusCode::BAD_REQUEST, format!("Invalid patch: {err}")).into_response(),
+        };
+        // Checks the revision this patch made, after the lock is released.
+        let (summary, patched) = {
+            let mut store = seq.lock().unwrap();
+            let summary = store.modify(|seq| seq.apply_patch(patch));
+            (summary, store.sequence())
+        };
+        for diagnostic in &patched.validate_with(&inventory, &rules).diagnostics {
+            log::warn!("{diagnostic}");
+        }
+        axum::Json(summary).into_response()
+    }
+
+    pub async fn display_sequence(State(seq): State<SequenceRef>, Query(window): Query<TimeWindow>) -> axum::response::Response {
+        if let Err(err) = window.check() {
+            return (StatusCode::BAD_REQUEST, err).into_response();
+        }
+        let seq_inner = seq.lock().unwrap().sequence();
+        if window.is_open() {
+            seq_inner.into_json().into_response()
+        } else {
+            seq_inner.cropped(&window).into_json().into_response()
+        }
+    }
+
+    pub async fn list_channels(State(seq): State<SequenceRef>) -> axum::response::Response {
+        axum::Json(seq.lock().unwrap().sequence().summaries()).into_response()
+    }
+
+    pub async fn display_channel(State(seq): State<SequenceRef>, Path((address, sigchan)): Path<(u8, u8)>) -> axum::response::Response {
+        match seq.lock().unwrap().sequence().channel(ChannelKey { address, sigchan }) {
+            Some(chan) => axum::Json(chan).into_response(),
+            None => channel_not_found(address, sigchan),
+        }
+    }
+
+    /// Plots a single channel on its own.
+    pub async fn display_channel_plot(State(seq): State<SequenceRef>, State(registry): State<RegistryRef>, State(inventory): State<InventoryRef>, Path((address, sigchan)): Path<(u8, u8)>, Query(opts): Query<RenderOptions>) -> axum::response::Response {
+        let (chan, revision) = {
+            let store = seq.lock().unwrap();
+            match store.sequence().channel(ChannelKey { address, sigchan }) {
+                Some(chan) => (chan.clone(), store.revision()),
+                None => return channel_not_found(address, sigchan),
+            }
+        };
+        let opts = RenderOptions { registry : Some(registry), inventory : Some(inventory), ..opts };
+        axum::response::Html(Sequence { seq_channel : vec![chan] }.to_html(&opts, revision)).into_response()
+    }
+
+    fn channel_not_found(address : u8, sigchan : u8) -> axum::response::Response {
+        (StatusCode::NOT_FOUND, format!("No channel {address}/{sigchan} in the sequence")).into_response()
+    }
+
+    /// Runs the sequence checks on the stored sequence, answering 422 if any fail.
+    pub async fn lint_sequence(State(seq): State<SequenceRef>, State(inventory): State<InventoryRef>, State(rules): State<RulesRef>) -> axum::response::Response {
+        let snapshot = seq.lock().unwrap().sequence();
+        let report = snapshot.validate_with(&inventory, &rules);
+        let status = if report.is_ok() { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY };
+        (status, axum::Json(report)).into_response()
+    }
+
+    /// A self-contained page for shot records, see `Sequence::to_archive_html`.
+    pub async fn export_sequence(State(seq): State<SequenceRef>, State(registry): State<RegistryRef>, State(inventory): State<InventoryRef>,
+            State(rules): State<RulesRef>) -> axum::response::Html<String> {
+        let seq = seq.lock().unwrap().sequence();
+        let opts = RenderOptions { registry : Some(registry), inventory : Some(inventory.clone()), ..RenderOptions::default() };
+        axum::response::Html(seq.to_archive_html(&opts, &inventory, &rules))
+    }
+
+    /// The worst-case quantisation error of every channel of the stored
+    /// sequence, see `Sequence::quantization_errors`.
+    pub async fn quantization_errors(State(seq): State<SequenceRef>, State(inventory): State<InventoryRef>) -> axum::Json<Vec<QuantizationError>> {
+        let snapshot = seq.lock().unwrap().sequence();
+        axum::Json(snapshot.quantization_errors(&inventory))
+    }
+
+    /// The hardware inventory sequences are checked against.
+    pub async fn list_devices(State(inventory): State<InventoryRef>) -> axum::Json<Inventory> {
+        axum::Json(Inventory::clone(&inventory))
+    }
+
+    /// Plots the sequence. Pages are cached per revision and query string, and
+    /// carry an ETag so unchanged plots are answered with 304 Not Modified.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn display_plot_content(State(seq): State<SequenceRef>, State(registry): State<RegistryRef>, State(inventory): State<InventoryRef>, RawQuery(query): RawQuery, headers: HeaderMap,
+            Query(filter): Query<ChannelFilter>, Query(window): Query<TimeWindow>, Query(opts): Query<RenderOptions>) -> axum::response::Response {
+        let opts = RenderOptions { registry : Some(registry), inventory : Some(inventory), ..opts };
+        if let Err(err) = window.check() {
+            return (StatusCode::BAD_REQUEST, err).into_response();
+        }
+        let key = format!("display?{}", query.unwrap_or_default());
+        cached_render(&seq, key, &headers, "text/html; charset=utf-8", |snapshot, revision| {
+            html_page(&snapshot.to_plot_view(&filter, &window, &opts).to_json(), PlotlyJs::Url(PLOTLY_JS_PATH), Some((EVENTS_PATH, revision)))
+        })
+    }
+
+    #[derive(Deserialize, Debug, Default)]
+    #[serde(default)]
+    pub struct FigureQuery {
+        /// `1` or `true` answers with an HTML `div` to embed instead of JSON.
+        pub embed : Option<String>,
+    }
+
+    /// The plotly figure (data and layout) that `/state/display` draws, for
+    /// external plotting clients. Takes the same query parameters, cached the same way.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn display_figure(State(seq): State<SequenceRef>, State(registry): State<RegistryRef>, State(inventory): State<InventoryRef>, RawQuery(query): RawQuery, headers: HeaderMap,
+            Query(filter): Query<ChannelFilter>, Query(window): Query<TimeWindow>, Query(opts): Query<RenderOptions>,
+            Query(figure): Query<FigureQuery>) -> axum::response::Response {
+        let opts = RenderOptions { registry : Some(registry), inventory : Some(inventory), ..opts };
+        let embed = matches!(figure.embed.as_deref(), Some("1" | "true"));
+        if let Err(err) = window.check() {
+            return (StatusCode::BAD_REQUEST, err).into_response();
+        }
+        let key = format!("figure?{}", query.unwrap_or_default());
+        let content_type = if embed { "text/html; charset=utf-8" } else { "application/json" };
+        cached_render(&seq, key, &headers, content_type, |snapshot, _| {
+            let figure = snapshot.to_plot_view(&filter, &window, &opts).to_json();
+            if embed { plot_div(&figure, "plotly-html-element") } else { figure }
+        })
+    }
+
+    /// Answers with the body `render` makes from the current sequence and its
+    /// revision, cached under `key` for this revision. Rendering happens outside the lock.
+    fn cached_render(seq : &SequenceRef, key : String, headers : &HeaderMap, content_type : &'static str,
+            render : impl FnOnce(&Sequence, u64) -> String) -> axum::response::Response {
+        let (snapshot, revision, cached) = {
+            let store = seq.lock().unwrap();
+            (store.sequence(), store.revision(), store.cached_page(&key))
+        };
+        let etag = revision_etag(revision);
+        let cache_headers = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, "no-cache".to_string())];
+        if matches_etag(headers, &etag) {
+            return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+        }
+        let page = match cached {
+            Some(page) => page,
+            None => {
+                let page = Arc::new(render(&snapshot, revision));
+                seq.lock().unwrap().cache_page(revision, key, page.clone());
+                page
+            }
+        };
+        (cache_headers, [(header::CONTENT_TYPE, content_type)], page.as_str().to_owned()).into_response()
+    }
+
+    /// Server-Sent Events announcing sequence revisions. The current revision is
+    /// sent on connect, then every new one as a `revision` event.
+    pub async fn sequence_events(State(seq): State<SequenceRef>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let revisions = seq.lock().unwrap().subscribe();
+        let stream = futures::stream::unfold((revisions, true), |(mut revisions, first)| async move {
+            if !first {
+                revisions.changed().await.ok()?;
+            }
+            let revision = *revisions.borrow_and_update();
+            Some((Ok(Event::default().event("revision").data(revision.to_string())), (revisions, false)))
+        });
+        Sse::new(stream).keep_alive(KeepAlive::default())
+    }
+
+    /// ETags include the server start time, so revisions from an earlier run never match.
+    fn revision_etag(revision : u64) -> String {
+        static STARTED : OnceLock<u64> = OnceLock::new();
+        let started = STARTED.get_or_init(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+        format!("\"{started}-{revision}\"")
     }
 
-    pub async fn display_plot_content(uri: Uri, State(seq): State<SequenceRef>, req: Request<Body>) -> axum::response::Html<String> {
-        println!("Trying to send to client");
-        let str = (*seq.clone().lock().unwrap()).to_html();
-        println!("{}", str);
-        axum::response::Html(str)
+    fn matches_etag(headers : &HeaderMap, etag : &str) -> bool {
+        headers.get_all(header::IF_NONE_MATCH).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|tag| tag.trim() == etag || tag.trim() == "*")
     }
-}}
\ No newline at end of file
+}}
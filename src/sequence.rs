@@ -1,52 +1,52 @@
-use serde::{de, Serialize, Deserialize};
+use serde::{de, ser, Serialize, Deserialize};
 use serde_with::{serde_as, BoolFromInt};
 use serde_json;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnalogSeq {
     pub amplitude   : Vec<f64>,
     pub times       : Vec<f64>,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DigitalSeq {
-    #[serde(deserialize_with = "deserialize_bool")]
+    #[serde(serialize_with = "serialize_bool", deserialize_with = "deserialize_bool")]
     pub value       : Vec<bool>,
     pub times       : Vec<f64>,
 }   
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RS485Seq {
-    #[serde(deserialize_with = "deserialize_str")]
+    #[serde(serialize_with = "serialize_str", deserialize_with = "deserialize_str")]
     pub command     : Vec<String>,
     pub times       : Vec<f64>,
 }     
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VCOSeq {
     pub frequency   : Vec<f64>,
     pub times       : Vec<f64>,
 }       
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DDSSeq {
     pub amplitude : Vec<f64>,
     pub frequency : Vec<f64>,
-    #[serde(deserialize_with = "deserialize_bool")]
+    #[serde(serialize_with = "serialize_bool", deserialize_with = "deserialize_bool")]
     pub feature_enable : Vec<bool>,
     pub feature_value  : Vec<f64>,
     pub times       : Vec<f64>,
 }       
 #[serde_as]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PulseGenSeq {
     #[serde(rename = "tDelay")]
-    time_delay : f64,
+    pub time_delay : f64,
     #[serde(rename = "tWidth")]
-    time_width : f64,
+    pub time_width : f64,
     #[serde_as(as = "BoolFromInt")]
-    polarity : bool,
+    pub polarity : bool,
 }  
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FreqFBSeq {
 }    
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum DeviceDependentData {
     Analog      (AnalogSeq      ),
     Digital     (DigitalSeq     ),
@@ -57,7 +57,80 @@ pub enum DeviceDependentData {
     #[serde(rename = "FreqFeedback")]
     FreqFB      (FreqFBSeq      ),
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// The device family of a channel, i.e. the variant of its `DeviceDependentData`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    Analog,
+    Digital,
+    RS485,
+    PLLVCO,
+    DDSRF,
+    PulseGen,
+    #[serde(rename = "FreqFeedback")]
+    FreqFB,
+}
+
+impl DeviceKind {
+    /// The name used for the variant in sequence JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceKind::Analog   => "Analog",
+            DeviceKind::Digital  => "Digital",
+            DeviceKind::RS485    => "RS485",
+            DeviceKind::PLLVCO   => "PLLVCO",
+            DeviceKind::DDSRF    => "DDSRF",
+            DeviceKind::PulseGen => "PulseGen",
+            DeviceKind::FreqFB   => "FreqFeedback",
+        }
+    }
+}
+
+impl std::str::FromStr for DeviceKind {
+    type Err = String;
+
+    /// Parses a device name as used in sequence JSON, ignoring case.
+    fn from_str(name : &str) -> Result<Self, Self::Err> {
+        [
+            DeviceKind::Analog, DeviceKind::Digital, DeviceKind::RS485, DeviceKind::PLLVCO,
+            DeviceKind::DDSRF, DeviceKind::PulseGen, DeviceKind::FreqFB,
+        ].into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| format!("unknown device kind `{name}`"))
+    }
+}
+
+impl std::fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl DeviceDependentData {
+    pub fn kind(&self) -> DeviceKind {
+        match self {
+            DeviceDependentData::Analog(_)   => DeviceKind::Analog,
+            DeviceDependentData::Digital(_)  => DeviceKind::Digital,
+            DeviceDependentData::RS485(_)    => DeviceKind::RS485,
+            DeviceDependentData::PLLVCO(_)   => DeviceKind::PLLVCO,
+            DeviceDependentData::DDSRF(_)    => DeviceKind::DDSRF,
+            DeviceDependentData::PulseGen(_) => DeviceKind::PulseGen,
+            DeviceDependentData::FreqFB(_)   => DeviceKind::FreqFB,
+        }
+    }
+    /// The time axis of the data, empty for devices without one.
+    pub fn times(&self) -> &[f64] {
+        match self {
+            DeviceDependentData::Analog(d)  => &d.times,
+            DeviceDependentData::Digital(d) => &d.times,
+            DeviceDependentData::RS485(d)   => &d.times,
+            DeviceDependentData::PLLVCO(d)  => &d.times,
+            DeviceDependentData::DDSRF(d)   => &d.times,
+            DeviceDependentData::PulseGen(_) | DeviceDependentData::FreqFB(_) => &[],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChannelSequence {
     #[serde(rename = "data")]
     pub device_dependent    : DeviceDependentData,
@@ -67,6 +140,46 @@ pub struct ChannelSequence {
     pub address             : u8,
 }
 
+/// Identifies a channel by board address and signal channel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChannelKey {
+    pub address     : u8,
+    pub sigchan     : u8,
+}
+
+/// An overview of one channel, without its data.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelSummary {
+    pub name        : String,
+    pub device      : DeviceKind,
+    pub address     : u8,
+    pub sigchan     : u8,
+    pub points      : usize,
+    /// Earliest and latest sequence time, absent for channels without a time axis.
+    pub t_start     : Option<f64>,
+    pub t_end       : Option<f64>,
+}
+
+impl ChannelSequence {
+    pub fn key(&self) -> ChannelKey {
+        ChannelKey { address : self.address, sigchan : self.index_sigchan }
+    }
+    pub fn summary(&self) -> ChannelSummary {
+        let times = self.device_dependent.times();
+        let t_start = times.iter().copied().reduce(f64::min);
+        let t_end = times.iter().copied().reduce(f64::max);
+        ChannelSummary {
+            name    : self.name.clone(),
+            device  : self.device_dependent.kind(),
+            address : self.address,
+            sigchan : self.index_sigchan,
+            points  : times.len(),
+            t_start,
+            t_end,
+        }
+    }
+}
+
 // How to map with result?
 
 fn deserialize_bool<'de, D>(deserializer: D) -> Result<Vec<bool>, D::Error>
@@ -75,7 +188,7 @@ where
 {
     let v: Vec<u8> = de::Deserialize::deserialize(deserializer)?;
     let b = v.into_iter()
-                        .map(|u| !(u == 0))
+                        .map(|u| u != 0)
                         .collect();
     Ok(b)
 }
@@ -91,7 +204,23 @@ where
     Ok(b)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Serialize back into the wire format, so stored sequences can be uploaded again.
+
+fn serialize_bool<S>(value: &[bool], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+{
+    serializer.collect_seq(value.iter().map(|&b| b as u8))
+}
+
+fn serialize_str<S>(value: &[String], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+{
+    serializer.collect_seq(value.iter().map(|s| s.as_bytes()))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Sequence {
     pub seq_channel : Vec<ChannelSequence>,
 }
@@ -103,10 +232,139 @@ impl Sequence {
     pub fn update_from_json(&mut self, js : &str) {
         self.seq_channel = serde_json::from_str(js).unwrap();
     }
+    /// Parses a sequence from either a plain JSON channel array or the
+    /// string-encoded form that senders POST to `/state` (see `test.json`).
+    pub fn from_json(js : &str) -> Result<Self, serde_json::Error> {
+        match serde_json::from_str(js)? {
+            serde_json::Value::String(inner) => Self::from_json(&inner),
+            serde_json::Value::Object(obj) => serde_json::from_value(serde_json::Value::Object(obj)),
+            value => Ok(Sequence{ seq_channel : serde_json::from_value(value)? }),
+        }
+    }
     pub fn into_json(&self) -> String {
         serde_json::to_string(&self.seq_channel).unwrap()
     }
     pub fn empty() -> Self {
         Sequence{ seq_channel : vec![] }
     }  
+    pub fn channel(&self, key : ChannelKey) -> Option<&ChannelSequence> {
+        self.seq_channel.iter().find(|chan| chan.key() == key)
+    }
+    pub fn summaries(&self) -> Vec<ChannelSummary> {
+        self.seq_channel.iter().map(ChannelSequence::summary).collect()
+    }
+}
+
+/// A partial update of the stored sequence. Channels in `remove` are dropped
+/// first, then every channel in `channels` replaces the stored channel with the
+/// same `(address, sigchan)` or is appended if there is none. Of channels with
+/// the same `(address, sigchan)` in one patch the last one is kept.
+#[derive(Deserialize, Debug, Default)]
+pub struct SequencePatch {
+    #[serde(default)]
+    pub channels    : Vec<ChannelSequence>,
+    #[serde(default)]
+    pub remove      : Vec<ChannelKey>,
+}
+
+/// What applying a `SequencePatch` changed.
+#[derive(Serialize, Debug, Default)]
+pub struct PatchSummary {
+    pub added       : Vec<ChannelKey>,
+    pub replaced    : Vec<ChannelKey>,
+    pub removed     : Vec<ChannelKey>,
+    /// Channels asked to be removed that were not in the sequence.
+    pub missing     : Vec<ChannelKey>,
+}
+
+impl SequencePatch {
+    /// Parses a patch, plain or string-encoded like the bodies POSTed to `/state`.
+    pub fn from_json(js : &str) -> Result<Self, serde_json::Error> {
+        match serde_json::from_str(js)? {
+            serde_json::Value::String(inner) => Self::from_json(&inner),
+            value => serde_json::from_value(value),
+        }
+    }
+}
+
+impl Sequence {
+    pub fn apply_patch(&mut self, patch : SequencePatch) -> PatchSummary {
+        let mut summary = PatchSummary::default();
+        for key in patch.remove {
+            let before = self.seq_channel.len();
+            self.seq_channel.retain(|chan| chan.key() != key);
+            if self.seq_channel.len() < before {
+                summary.removed.push(key);
+            } else {
+                summary.missing.push(key);
+            }
+        }
+        for chan in patch.channels {
+            let key = chan.key();
+            match self.seq_channel.iter_mut().find(|old| old.key() == key) {
+                Some(old) => {
+                    *old = chan;
+                    // Reported once, as added if an earlier entry of this patch added it.
+                    if !summary.added.contains(&key) && !summary.replaced.contains(&key) {
+                        summary.replaced.push(key);
+                    }
+                }
+                None => {
+                    self.seq_channel.push(chan);
+                    summary.added.push(key);
+                }
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analog(address : u8, name : &str) -> ChannelSequence {
+        ChannelSequence {
+            device_dependent : DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0.], times : vec![0.] }),
+            name : name.into(), index_sigchan : 0, address,
+        }
+    }
+
+    fn key(address : u8) -> ChannelKey {
+        ChannelKey { address, sigchan : 0 }
+    }
+
+    fn names(seq : &Sequence) -> Vec<&str> {
+        seq.seq_channel.iter().map(|chan| chan.name.as_str()).collect()
+    }
+
+    #[test]
+    fn patches_replace_add_and_remove_channels() {
+        let mut seq = Sequence { seq_channel : vec![analog(1, "a"), analog(2, "b"), analog(3, "c")] };
+        let patch = SequencePatch { channels : vec![analog(2, "b2"), analog(4, "d")], remove : vec![key(1), key(5)] };
+        let summary = seq.apply_patch(patch);
+        assert_eq!(names(&seq), ["b2", "c", "d"]);
+        assert_eq!(summary.added, [key(4)]);
+        assert_eq!(summary.replaced, [key(2)]);
+        assert_eq!(summary.removed, [key(1)]);
+        assert_eq!(summary.missing, [key(5)]);
+    }
+
+    #[test]
+    fn removals_come_before_the_channels() {
+        let mut seq = Sequence { seq_channel : vec![analog(1, "a")] };
+        let summary = seq.apply_patch(SequencePatch { channels : vec![analog(1, "a2")], remove : vec![key(1)] });
+        assert_eq!(names(&seq), ["a2"]);
+        assert_eq!((summary.removed, summary.added), (vec![key(1)], vec![key(1)]));
+    }
+
+    #[test]
+    fn the_last_of_repeated_channels_is_kept_and_reported_once() {
+        let mut seq = Sequence { seq_channel : vec![analog(1, "a")] };
+        let channels = vec![analog(1, "a2"), analog(2, "b"), analog(1, "a3"), analog(2, "b2")];
+        let summary = seq.apply_patch(SequencePatch { channels, remove : vec![] });
+        assert_eq!(names(&seq), ["a3", "b2"]);
+        assert_eq!(summary.added, [key(2)]);
+        assert_eq!(summary.replaced, [key(1)]);
+    }
 }
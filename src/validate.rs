@@ -0,0 +1,611 @@
+//! Consistency checks for uploaded sequences. The same checks back the
+//! server's `/state/lint` endpoint and the `seqlines lint` subcommand.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::inventory::{Board, Inventory};
+use crate::quantize;
+use crate::rules::RuleSet;
+use crate::sequence::{ChannelSequence, DeviceDependentData, DeviceKind, DigitalSeq, Sequence};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity    : Severity,
+    /// Short name of the check that fired, e.g. `monotonic-times`.
+    pub check       : &'static str,
+    pub address     : Option<u8>,
+    pub sigchan     : Option<u8>,
+    pub name        : Option<String>,
+    /// Sequence time of the first offending point, if the check is about one.
+    pub time        : Option<f64>,
+    pub message     : String,
+}
+
+impl Diagnostic {
+    pub fn error(check : &'static str, message : impl Into<String>) -> Self {
+        Self::new(Severity::Error, check, message.into())
+    }
+    pub fn warning(check : &'static str, message : impl Into<String>) -> Self {
+        Self::new(Severity::Warning, check, message.into())
+    }
+    fn new(severity : Severity, check : &'static str, message : String) -> Self {
+        Diagnostic { severity, check, address : None, sigchan : None, name : None, time : None, message }
+    }
+    /// Attributes the diagnostic to a channel.
+    pub fn on(mut self, chan : &ChannelSequence) -> Self {
+        self.address = Some(chan.address);
+        self.sigchan = Some(chan.index_sigchan);
+        self.name = Some(chan.name.clone());
+        self
+    }
+    pub fn at(mut self, time : f64) -> Self {
+        self.time = Some(time);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}[{}]", self.check)?;
+        if let (Some(address), Some(sigchan)) = (self.address, self.sigchan) {
+            write!(f, " {address}/{sigchan}")?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, " \"{name}\"")?;
+        }
+        if let Some(time) = self.time {
+            write!(f, " at t={time}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Report {
+    pub errors      : usize,
+    pub warnings    : usize,
+    pub diagnostics : Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn push(&mut self, diagnostic : Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+        }
+        self.diagnostics.push(diagnostic);
+    }
+    pub fn is_ok(&self) -> bool {
+        self.errors == 0
+    }
+}
+
+/// What a device can physically output. Ranges are `[min, max]` in JSON;
+/// rates are per time unit of the sequence.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct DeviceLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude   : Option<(f64, f64)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency   : Option<(f64, f64)>,
+    /// Smallest amplitude step the output can make.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude_resolution : Option<f64>,
+    /// Smallest frequency step the output can make.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_resolution : Option<f64>,
+    /// Shortest time between two sequence points.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_time_step : Option<f64>,
+    /// Fastest change between two points, of the amplitude for analog and
+    /// DDS channels and of the frequency for VCOs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_slew    : Option<f64>,
+    /// Period of the sequencer clock; points are output on its ticks. Only
+    /// used by the quantisation preview, see `quantize`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_tick  : Option<f64>,
+    /// Shortest high pulse a digital output can follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_high    : Option<f64>,
+    /// Shortest low pulse a digital output can follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_low     : Option<f64>,
+    /// Shortest time between two edges of a digital output, whatever the
+    /// level between them; applies where it is stricter than `min_high` or
+    /// `min_low`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_edge_spacing : Option<f64>,
+}
+
+impl DeviceLimits {
+    /// Built-in ranges assumed until an inventory gives those of the actual
+    /// boards: ±10 V analog outputs, DDS amplitude as a fraction of full scale,
+    /// DDS frequencies up to 500 MHz and non-negative VCO frequencies. Points
+    /// outside them are only warned about, see `check_limits`.
+    pub fn default_for(kind : DeviceKind) -> Self {
+        match kind {
+            DeviceKind::Analog => DeviceLimits { amplitude : Some((-10., 10.)), ..DeviceLimits::default() },
+            DeviceKind::DDSRF => DeviceLimits { amplitude : Some((0., 1.)), frequency : Some((0., 500.)), ..DeviceLimits::default() },
+            DeviceKind::PLLVCO => DeviceLimits { frequency : Some((0., f64::INFINITY)), ..DeviceLimits::default() },
+            _ => DeviceLimits::default(),
+        }
+    }
+
+    /// These limits, with those not set taken from `fallback`.
+    pub fn or(&self, fallback : &DeviceLimits) -> DeviceLimits {
+        DeviceLimits {
+            amplitude : self.amplitude.or(fallback.amplitude),
+            frequency : self.frequency.or(fallback.frequency),
+            amplitude_resolution : self.amplitude_resolution.or(fallback.amplitude_resolution),
+            frequency_resolution : self.frequency_resolution.or(fallback.frequency_resolution),
+            min_time_step : self.min_time_step.or(fallback.min_time_step),
+            max_slew : self.max_slew.or(fallback.max_slew),
+            clock_tick : self.clock_tick.or(fallback.clock_tick),
+            min_high : self.min_high.or(fallback.min_high),
+            min_low : self.min_low.or(fallback.min_low),
+            min_edge_spacing : self.min_edge_spacing.or(fallback.min_edge_spacing),
+        }
+    }
+}
+
+impl Sequence {
+    /// Runs every check on the sequence. Problems are reported, never fixed.
+    pub fn validate(&self) -> Report {
+        self.validate_with(&Inventory::default(), &RuleSet::default())
+    }
+
+    /// Runs every check, and with a non-empty `inventory` also checks that
+    /// each channel addresses an existing channel of a board of its device
+    /// kind, using that board's limits. Then checks the cross-channel `rules`.
+    pub fn validate_with(&self, inventory : &Inventory, rules : &RuleSet) -> Report {
+        let mut report = Report::default();
+        check_duplicates(self, &mut report);
+        for chan in &self.seq_channel {
+            check_lengths(chan, &mut report);
+            check_times(chan, &mut report);
+            let board = check_board(chan, inventory, &mut report);
+            let defined = board.map(|board| board.defined_limits_for(chan.index_sigchan)).unwrap_or_default();
+            check_limits(chan, &defined, &mut report);
+        }
+        rules.check(self, &mut report);
+        report
+    }
+}
+
+fn check_duplicates(seq : &Sequence, report : &mut Report) {
+    let mut by_address : HashMap<(u8, u8), &str> = HashMap::new();
+    let mut by_name : HashMap<&str, (u8, u8)> = HashMap::new();
+    for chan in &seq.seq_channel {
+        let key = (chan.address, chan.index_sigchan);
+        if let Some(first) = by_address.insert(key, &chan.name) {
+            report.push(Diagnostic::error("duplicate-channel",
+                format!("channel is defined more than once, first as \"{first}\"")).on(chan));
+        }
+        if let Some((address, sigchan)) = by_name.insert(&chan.name, key) {
+            if (address, sigchan) != key {
+                report.push(Diagnostic::warning("duplicate-name",
+                    format!("name is also used by channel {address}/{sigchan}")).on(chan));
+            }
+        }
+    }
+}
+
+/// The board `chan` is on, if the inventory has one of its device kind at
+/// its address.
+fn check_board<'a>(chan : &ChannelSequence, inventory : &'a Inventory, report : &mut Report) -> Option<&'a Board> {
+    if inventory.is_empty() {
+        return None;
+    }
+    let kind = chan.device_dependent.kind();
+    let Some(board) = inventory.board(chan.address) else {
+        report.push(Diagnostic::error("unknown-address",
+            format!("no board at address {} in the inventory", chan.address)).on(chan));
+        return None;
+    };
+    if board.device != kind {
+        report.push(Diagnostic::error("device-mismatch",
+            format!("{kind} data for the {} board at address {}", board.device, board.address)).on(chan));
+        return None;
+    }
+    if chan.index_sigchan >= board.channels {
+        report.push(Diagnostic::error("sigchan-range",
+            format!("sigchan {} beyond the {} channel(s) of the board", chan.index_sigchan, board.channels)).on(chan));
+    }
+    Some(board)
+}
+
+/// Lengths of the value arrays that must run parallel to `times`.
+fn value_lengths(data : &DeviceDependentData) -> Vec<(&'static str, usize)> {
+    match data {
+        DeviceDependentData::Analog(d)  => vec![("amplitude", d.amplitude.len())],
+        DeviceDependentData::Digital(d) => vec![("value", d.value.len())],
+        DeviceDependentData::RS485(d)   => vec![("command", d.command.len())],
+        DeviceDependentData::PLLVCO(d)  => vec![("frequency", d.frequency.len())],
+        DeviceDependentData::DDSRF(d)   => vec![
+            ("amplitude", d.amplitude.len()),
+            ("frequency", d.frequency.len()),
+            ("feature_enable", d.feature_enable.len()),
+            ("feature_value", d.feature_value.len()),
+        ],
+        DeviceDependentData::PulseGen(_) | DeviceDependentData::FreqFB(_) => vec![],
+    }
+}
+
+fn check_lengths(chan : &ChannelSequence, report : &mut Report) {
+    let n_times = chan.device_dependent.times().len();
+    for (field, len) in value_lengths(&chan.device_dependent) {
+        if len != n_times {
+            report.push(Diagnostic::error("array-length",
+                format!("`{field}` has {len} entries but `times` has {n_times}")).on(chan));
+        }
+    }
+}
+
+fn check_times(chan : &ChannelSequence, report : &mut Report) {
+    let times = chan.device_dependent.times();
+    if let Some(i) = times.iter().position(|t| !t.is_finite()) {
+        report.push(Diagnostic::error("finite-times",
+            format!("time #{i} is {}", times[i])).on(chan));
+        return;
+    }
+    let steps_back : Vec<usize> = (1..times.len()).filter(|&i| times[i] < times[i - 1]).collect();
+    if let Some(&i) = steps_back.first() {
+        let more = match steps_back.len() - 1 {
+            0 => String::new(),
+            n => format!(" ({n} more later)"),
+        };
+        report.push(Diagnostic::error("monotonic-times",
+            format!("time goes back from {} to {}{more}", times[i - 1], times[i])).on(chan).at(times[i - 1]));
+    }
+    if let Some(&t) = times.iter().find(|&&t| t < 0.) {
+        report.push(Diagnostic::warning("negative-time", "sequence point before t=0").on(chan).at(t));
+    }
+}
+
+/// A point breaking a device limit. The sequence checks report the first of
+/// each kind, the plot marks all of them.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub severity    : Severity,
+    /// `device-limit`, `resolution`, `time-step`, `slew-rate`, `pulse-width`
+    /// or `edge-spacing`.
+    pub check       : &'static str,
+    /// The quantity at fault, `amplitude` or `frequency`, or `pulse` for a
+    /// digital pulse starting at `time` with the level `value`.
+    pub field       : &'static str,
+    pub time        : f64,
+    pub value       : f64,
+    pub message     : String,
+}
+
+/// Every point of `data` breaking `limits`. Slew and time step limits apply
+/// to the amplitude of analog and DDS channels and to the frequency of VCOs,
+/// pulse limits to digital channels.
+pub fn limit_violations(data : &DeviceDependentData, limits : &DeviceLimits) -> Vec<Violation> {
+    let mut found = Vec::new();
+    match data {
+        DeviceDependentData::Analog(d) => {
+            check_series("amplitude", &d.times, &d.amplitude, limits.amplitude, limits.amplitude_resolution, limits, &mut found);
+        }
+        DeviceDependentData::DDSRF(d) => {
+            check_series("amplitude", &d.times, &d.amplitude, limits.amplitude, limits.amplitude_resolution, limits, &mut found);
+            check_series("frequency", &d.times, &d.frequency, limits.frequency, limits.frequency_resolution, &DeviceLimits::default(), &mut found);
+        }
+        DeviceDependentData::PLLVCO(d) => {
+            check_series("frequency", &d.times, &d.frequency, limits.frequency, limits.frequency_resolution, limits, &mut found);
+        }
+        DeviceDependentData::Digital(d) => check_pulses(d, limits, &mut found),
+        _ => {}
+    }
+    found
+}
+
+/// Checks one value series for range and resolution, and for the time step
+/// and slew limits of `rate_limits`. Steps back in time have no rate, they
+/// are left to `check_times`.
+fn check_series(field : &'static str, times : &[f64], values : &[f64], range : Option<(f64, f64)>, resolution : Option<f64>,
+        rate_limits : &DeviceLimits, found : &mut Vec<Violation>) {
+    let violation = |severity, check, time, value, message| Violation { severity, check, field, time, value, message };
+    for (&t, &v) in times.iter().zip(values) {
+        if let Some((min, max)) = range.filter(|(min, max)| !(min..=max).contains(&&v)) {
+            found.push(violation(Severity::Error, "device-limit", t, v, format!("{field} {v} outside [{min}, {max}]")));
+        }
+        if let Some(step) = resolution.filter(|&step| step > 0.) {
+            let steps = v / step;
+            if (steps - steps.round()).abs() > 1e-6 {
+                found.push(violation(Severity::Warning, "resolution", t, v,
+                    format!("{field} {v} is not a multiple of the resolution {step}, it will output as {}", quantize::snap(v, Some(step)))));
+            }
+        }
+    }
+    for i in 1..times.len().min(values.len()) {
+        let (dt, dv) = (times[i] - times[i - 1], values[i] - values[i - 1]);
+        if dt < 0. {
+            continue;
+        }
+        if let Some(min_step) = rate_limits.min_time_step.filter(|&min_step| dt < min_step) {
+            found.push(violation(Severity::Error, "time-step", times[i], values[i],
+                format!("points {dt} apart, closer than the minimum step {min_step}")));
+        }
+        if let Some(max_slew) = rate_limits.max_slew.filter(|&max_slew| dv != 0. && dv.abs() > max_slew * dt) {
+            found.push(violation(Severity::Error, "slew-rate", times[i], values[i],
+                format!("{field} changes by {dv} in {dt}, faster than the maximum slew rate {max_slew}")));
+        }
+    }
+}
+
+/// Checks the pulses of a digital channel, the intervals between its edges.
+/// The output idles low before the first point, of points at the same time
+/// the last one counts, and the level after the last edge has no end to
+/// check. A pulse shorter than both the minimum for its level and the edge
+/// spacing is reported once, against the stricter of the two.
+fn check_pulses(wave : &DigitalSeq, limits : &DeviceLimits, found : &mut Vec<Violation>) {
+    let points : Vec<(f64, bool)> = wave.times.iter().copied().zip(wave.value.iter().copied()).collect();
+    let mut level = false;
+    let mut edges : Vec<(f64, bool)> = Vec::new();
+    for (i, &(t, high)) in points.iter().enumerate() {
+        let overridden = points.get(i + 1).is_some_and(|&(next, _)| next == t);
+        if high != level && !overridden {
+            edges.push((t, high));
+            level = high;
+        }
+    }
+    for pair in edges.windows(2) {
+        let ((start, high), (end, _)) = (pair[0], pair[1]);
+        let width = end - start;
+        let (name, min_width) = if high { ("high", limits.min_high) } else { ("low", limits.min_low) };
+        let by_level = min_width.map(|min_width| ("pulse-width", min_width,
+            format!("{name} pulse of {width}, shorter than the minimum {name} time {min_width}")));
+        let by_spacing = limits.min_edge_spacing.map(|min_spacing| ("edge-spacing", min_spacing,
+            format!("edges {width} apart, closer than the minimum spacing {min_spacing}")));
+        let strictest = [by_level, by_spacing].into_iter().flatten().max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((check, _, message)) = strictest.filter(|&(_, min, _)| width < min) {
+            found.push(Violation {
+                severity : Severity::Error, check, field : "pulse", time : start, value : if high { 1. } else { 0. }, message,
+            });
+        }
+    }
+}
+
+/// Checks `chan` against the `defined` limits of its board, completed by the
+/// built-in ones. Points outside a built-in range are warnings rather than
+/// errors, as the range may not be that of the actual board.
+fn check_limits(chan : &ChannelSequence, defined : &DeviceLimits, report : &mut Report) {
+    let limits = defined.or(&DeviceLimits::default_for(chan.device_dependent.kind()));
+    let violations = limit_violations(&chan.device_dependent, &limits);
+    let mut reported : Vec<(&str, &str)> = Vec::new();
+    for violation in &violations {
+        // Short pulses are few and each needs fixing, so every one is
+        // reported; of other violations only the first of each kind.
+        let message = if violation.field == "pulse" {
+            violation.message.clone()
+        } else if reported.contains(&(violation.check, violation.field)) {
+            continue;
+        } else {
+            reported.push((violation.check, violation.field));
+            let count = violations.iter().filter(|v| (v.check, v.field) == (violation.check, violation.field)).count();
+            format!("{} ({count} point(s) in total)", violation.message)
+        };
+        let range = match violation.field {
+            "amplitude" => defined.amplitude,
+            "frequency" => defined.frequency,
+            _ => None,
+        };
+        let diagnostic = match violation.severity {
+            Severity::Error if violation.check == "device-limit" && range.is_none() =>
+                Diagnostic::warning(violation.check, format!("{message}; the range is built in, not from the inventory")),
+            Severity::Error => Diagnostic::error(violation.check, message),
+            Severity::Warning => Diagnostic::warning(violation.check, message),
+        };
+        report.push(diagnostic.on(chan).at(violation.time));
+    }
+    if let DeviceDependentData::PulseGen(d) = &chan.device_dependent {
+        if !(d.time_delay >= 0. && d.time_width >= 0.) {
+            report.push(Diagnostic::error("device-limit",
+                format!("pulse delay {} and width {} must not be negative", d.time_delay, d.time_width)).on(chan));
+        }
+    }
+}
+
+/// The limits that apply to `chan`: those of its board in `inventory` if
+/// there is one of its device kind, else the built-in ones.
+pub fn channel_limits(chan : &ChannelSequence, inventory : &Inventory) -> DeviceLimits {
+    let kind = chan.device_dependent.kind();
+    match inventory.board(chan.address) {
+        Some(board) if board.device == kind => board.limits_for(chan.index_sigchan),
+        _ => DeviceLimits::default_for(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::{AnalogSeq, DDSSeq};
+
+    fn channel(address : u8, sigchan : u8, name : &str, data : DeviceDependentData) -> ChannelSequence {
+        ChannelSequence { device_dependent : data, name : name.into(), index_sigchan : sigchan, address }
+    }
+
+    fn analog(times : Vec<f64>, amplitude : Vec<f64>) -> DeviceDependentData {
+        DeviceDependentData::Analog(AnalogSeq { amplitude, times })
+    }
+
+    /// The severity, check and time of every diagnostic.
+    fn diagnostics(channels : Vec<ChannelSequence>, inventory : &Inventory) -> Vec<(Severity, &'static str, Option<f64>)> {
+        let report = Sequence { seq_channel : channels }.validate_with(inventory, &RuleSet::default());
+        report.diagnostics.iter().map(|d| (d.severity, d.check, d.time)).collect()
+    }
+
+    fn inventory(json : &str) -> Inventory {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn value_arrays_must_match_the_times() {
+        let short = channel(42, 0, "coil", analog(vec![0., 1.], vec![0.]));
+        let dds = channel(50, 0, "rf", DeviceDependentData::DDSRF(DDSSeq {
+            amplitude : vec![0.5], frequency : vec![80.], feature_enable : vec![], feature_value : vec![0.], times : vec![0.],
+        }));
+        assert_eq!(diagnostics(vec![short, dds], &Inventory::default()),
+            [(Severity::Error, "array-length", None), (Severity::Error, "array-length", None)]);
+    }
+
+    #[test]
+    fn times_must_be_finite_and_monotonic() {
+        // A NaN stops the time checks, whatever else is wrong.
+        let nan = channel(42, 0, "nan", analog(vec![-1., f64::NAN, 0.], vec![0.; 3]));
+        assert_eq!(diagnostics(vec![nan], &Inventory::default()), [(Severity::Error, "finite-times", None)]);
+        // Only the first step back is reported, at the time before it.
+        let back = channel(42, 0, "back", analog(vec![0., 2., 1., 3., 2.5], vec![0.; 5]));
+        let report = Sequence { seq_channel : vec![back] }.validate();
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.diagnostics[0].time, Some(2.));
+        assert!(report.diagnostics[0].message.ends_with("(1 more later)"), "{}", report.diagnostics[0].message);
+    }
+
+    #[test]
+    fn negative_times_are_warnings() {
+        let early = channel(42, 0, "early", analog(vec![-0.5, 0., 1.], vec![0.; 3]));
+        assert_eq!(diagnostics(vec![early], &Inventory::default()), [(Severity::Warning, "negative-time", Some(-0.5))]);
+    }
+
+    #[test]
+    fn channels_and_names_are_unique() {
+        let channels = vec![
+            channel(42, 0, "coil", analog(vec![0.], vec![0.])),
+            channel(42, 0, "coil", analog(vec![0.], vec![1.])),
+            channel(42, 1, "coil", analog(vec![0.], vec![0.])),
+        ];
+        assert_eq!(diagnostics(channels, &Inventory::default()),
+            [(Severity::Error, "duplicate-channel", None), (Severity::Warning, "duplicate-name", None)]);
+    }
+
+    #[test]
+    fn built_in_ranges_are_warnings_until_the_inventory_gives_one() {
+        let coil = || channel(42, 0, "coil", analog(vec![0., 1.], vec![0., 12.]));
+        assert_eq!(diagnostics(vec![coil()], &Inventory::default()), [(Severity::Warning, "device-limit", Some(1.))]);
+        let no_range = inventory(r#"{"boards": [{"address": 42, "device": "Analog", "channels": 8}]}"#);
+        assert_eq!(diagnostics(vec![coil()], &no_range), [(Severity::Warning, "device-limit", Some(1.))]);
+        let range = inventory(r#"{"boards": [{"address": 42, "device": "Analog", "channels": 8,
+            "limits": {"amplitude": [-15, 15]}, "channel_limits": {"0": {"amplitude": [-5, 5]}}}]}"#);
+        assert_eq!(diagnostics(vec![coil()], &range), [(Severity::Error, "device-limit", Some(1.))]);
+    }
+
+    #[test]
+    fn channels_must_be_on_a_board_of_their_kind() {
+        let boards = inventory(r#"{"boards": [{"address": 42, "device": "Analog", "channels": 2},
+            {"address": 64, "device": "Digital", "channels": 16}]}"#);
+        let digital = |address, sigchan| channel(address, sigchan, "shutter",
+            DeviceDependentData::Digital(DigitalSeq { value : vec![true], times : vec![0.] }));
+        assert_eq!(diagnostics(vec![digital(64, 15)], &boards), []);
+        assert_eq!(diagnostics(vec![digital(65, 0)], &boards), [(Severity::Error, "unknown-address", None)]);
+        assert_eq!(diagnostics(vec![digital(42, 0)], &boards), [(Severity::Error, "device-mismatch", None)]);
+        assert_eq!(diagnostics(vec![digital(64, 16)], &boards), [(Severity::Error, "sigchan-range", None)]);
+        // An empty inventory checks no boards.
+        assert_eq!(diagnostics(vec![digital(65, 0)], &Inventory::default()), []);
+    }
+
+    #[test]
+    fn channels_beyond_the_board_are_still_checked_against_its_limits() {
+        let boards = inventory(r#"{"boards": [{"address": 42, "device": "Analog", "channels": 2, "limits": {"amplitude": [-1, 1]}}]}"#);
+        let coil = channel(42, 2, "coil", analog(vec![0.], vec![2.]));
+        assert_eq!(diagnostics(vec![coil], &boards),
+            [(Severity::Error, "sigchan-range", None), (Severity::Error, "device-limit", Some(0.))]);
+    }
+
+    fn series(times : Vec<f64>, amplitude : Vec<f64>, limits : &DeviceLimits) -> Vec<(&'static str, f64, f64)> {
+        limit_violations(&analog(times, amplitude), limits).into_iter().map(|v| (v.check, v.time, v.value)).collect()
+    }
+
+    #[test]
+    fn series_values_stay_in_range() {
+        let limits = DeviceLimits { amplitude : Some((-1., 1.)), ..DeviceLimits::default() };
+        assert_eq!(series(vec![0., 1., 2., 3.], vec![-1., 1.5, 1., -2.], &limits), [("device-limit", 1., 1.5), ("device-limit", 3., -2.)]);
+    }
+
+    #[test]
+    fn series_values_are_multiples_of_the_resolution() {
+        let limits = DeviceLimits { amplitude_resolution : Some(0.25), ..DeviceLimits::default() };
+        let found = limit_violations(&analog(vec![0., 1., 2.], vec![0.5, 0.3, -0.75]), &limits);
+        assert_eq!(found.iter().map(|v| (v.severity, v.check, v.time)).collect::<Vec<_>>(), [(Severity::Warning, "resolution", 1.)]);
+        assert!(found[0].message.ends_with("it will output as 0.25"), "{}", found[0].message);
+    }
+
+    #[test]
+    fn series_points_keep_the_minimum_time_step() {
+        let limits = DeviceLimits { min_time_step : Some(0.5), ..DeviceLimits::default() };
+        assert_eq!(series(vec![0., 1., 1.2, 2.], vec![0.; 4], &limits), [("time-step", 1.2, 0.)]);
+    }
+
+    #[test]
+    fn series_changes_keep_the_maximum_slew() {
+        let limits = DeviceLimits { max_slew : Some(2.), ..DeviceLimits::default() };
+        // 2 per unit is fine, 3 per unit and a jump are not.
+        assert_eq!(series(vec![0., 1., 2., 2.], vec![0., 2., 5., 6.], &limits), [("slew-rate", 2., 5.), ("slew-rate", 2., 6.)]);
+    }
+
+    #[test]
+    fn steps_back_in_time_have_no_rate() {
+        let limits = DeviceLimits { min_time_step : Some(0.5), max_slew : Some(1.), ..DeviceLimits::default() };
+        assert_eq!(series(vec![0., 2., 1., 3.], vec![0., 1., 0., 1.], &limits), []);
+    }
+
+    fn pulses(times : Vec<f64>, value : Vec<bool>, limits : &DeviceLimits) -> Vec<(&'static str, f64, f64)> {
+        let data = DeviceDependentData::Digital(DigitalSeq { value, times });
+        limit_violations(&data, limits).into_iter().map(|v| (v.check, v.time, v.value)).collect()
+    }
+
+    fn shutter() -> DeviceLimits {
+        DeviceLimits { min_high : Some(0.5), min_low : Some(0.3), ..DeviceLimits::default() }
+    }
+
+    #[test]
+    fn pulses_start_from_an_idle_low_output() {
+        // High from the first point: a rising edge out of idle.
+        assert_eq!(pulses(vec![1., 1.2], vec![true, false], &shutter()), [("pulse-width", 1., 1.)]);
+        // Low from the first point is no edge, so the short low before the rise is fine.
+        assert_eq!(pulses(vec![0., 0.1, 1.], vec![false, true, false], &shutter()), []);
+    }
+
+    #[test]
+    fn pulses_check_each_level_against_its_minimum() {
+        let found = pulses(vec![0., 1., 1.2, 1.4, 3.], vec![false, true, false, true, false], &shutter());
+        assert_eq!(found, [("pulse-width", 1., 1.), ("pulse-width", 1.2, 0.)]);
+    }
+
+    #[test]
+    fn points_at_the_same_time_keep_the_last_value() {
+        assert_eq!(pulses(vec![1., 1., 2.], vec![true, false, false], &shutter()), []);
+        assert_eq!(pulses(vec![1., 1., 1.2], vec![false, true, false], &shutter()), [("pulse-width", 1., 1.)]);
+    }
+
+    #[test]
+    fn the_last_level_is_not_checked() {
+        assert_eq!(pulses(vec![0., 5.], vec![false, true], &shutter()), []);
+        assert_eq!(pulses(vec![1., 3., 3.1], vec![true, false, true], &shutter()), [("pulse-width", 3., 0.)]);
+    }
+
+    #[test]
+    fn short_pulses_are_reported_once_against_the_stricter_limit() {
+        let spacing = DeviceLimits { min_edge_spacing : Some(0.4), ..shutter() };
+        // High 0.2: min_high 0.5 is stricter. Low 0.35: only the spacing 0.4 is broken.
+        let found = pulses(vec![1., 1.2, 1.55, 3.], vec![true, false, true, false], &spacing);
+        assert_eq!(found, [("pulse-width", 1., 1.), ("edge-spacing", 1.2, 0.)]);
+    }
+}
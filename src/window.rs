@@ -0,0 +1,188 @@
+//! Cropping a sequence to a time window, e.g. from the query string of
+//! `/state/display?t0=1.5&t1=3.5`.
+
+use serde::Deserialize;
+
+use crate::sequence::{AnalogSeq, DDSSeq, DeviceDependentData, DigitalSeq, RS485Seq, Sequence, VCOSeq};
+
+/// An inclusive time window; an absent bound leaves that side open.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct TimeWindow {
+    pub t0      : Option<f64>,
+    pub t1      : Option<f64>,
+}
+
+impl TimeWindow {
+    pub fn is_open(&self) -> bool {
+        self.t0.is_none() && self.t1.is_none()
+    }
+
+    /// Rejects windows that end before they start.
+    pub fn check(&self) -> Result<(), String> {
+        match (self.t0, self.t1) {
+            (Some(t0), Some(t1)) if t0 > t1 => Err(format!("Time window ends before it starts: t0={t0} is after t1={t1}")),
+            _ => Ok(()),
+        }
+    }
+
+    /// The x-axis range showing the window, with open sides taken from the
+    /// extent of `seq`.
+    pub fn x_range(&self, seq : &Sequence) -> Option<(f64, f64)> {
+        if self.is_open() {
+            return None;
+        }
+        let times = seq.seq_channel.iter().flat_map(|chan| chan.device_dependent.times().iter().copied());
+        let (min, max) = times.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), t| (lo.min(t), hi.max(t)));
+        let start = self.t0.unwrap_or(min);
+        let end = self.t1.unwrap_or(max);
+        (start.is_finite() && end.is_finite()).then_some((start, end))
+    }
+
+    /// The points to keep from `times` as `(source index, new time)` pairs.
+    /// The point before the window is moved to `t0` and the last point inside
+    /// it is extended to `t1`, so traces still reach the window edges; see
+    /// `crop` for the values there.
+    fn plan(&self, times : &[f64]) -> Vec<(usize, f64)> {
+        let t0 = self.t0.unwrap_or(f64::NEG_INFINITY);
+        let t1 = self.t1.unwrap_or(f64::INFINITY);
+        let mut plan = Vec::new();
+        if let Some(held) = times.iter().rposition(|&t| t < t0) {
+            plan.push((held, t0));
+        }
+        plan.extend(times.iter().enumerate().filter(|&(_, &t)| t0 <= t && t <= t1).map(|(i, &t)| (i, t)));
+        match plan.last() {
+            Some(&(last, t)) if t < t1 && t1.is_finite() => plan.push((last, t1)),
+            _ => {}
+        }
+        plan
+    }
+
+    /// `data` within the window. Analog, DDS and VCO values are drawn as
+    /// lines between points, so their values at the edges are interpolated;
+    /// digital levels and the DDS feature are held from the point before.
+    pub fn crop(&self, data : &DeviceDependentData) -> DeviceDependentData {
+        let source_times = data.times();
+        let plan = self.plan(source_times);
+        let times = || plan.iter().map(|&(_, t)| t).collect::<Vec<_>>();
+        fn pick<T : Clone>(plan : &[(usize, f64)], values : &[T]) -> Vec<T> {
+            plan.iter().filter_map(|&(i, _)| values.get(i).cloned()).collect()
+        }
+        let line = |values : &[f64]| plan.iter().filter_map(|&(i, t)| line_at(source_times, values, i, t)).collect::<Vec<_>>();
+        match data {
+            DeviceDependentData::Analog(d) => DeviceDependentData::Analog(AnalogSeq {
+                amplitude : line(&d.amplitude),
+                times : times(),
+            }),
+            DeviceDependentData::Digital(d) => DeviceDependentData::Digital(DigitalSeq {
+                value : pick(&plan, &d.value),
+                times : times(),
+            }),
+            DeviceDependentData::PLLVCO(d) => DeviceDependentData::PLLVCO(VCOSeq {
+                frequency : line(&d.frequency),
+                times : times(),
+            }),
+            DeviceDependentData::DDSRF(d) => DeviceDependentData::DDSRF(DDSSeq {
+                amplitude : line(&d.amplitude),
+                frequency : line(&d.frequency),
+                feature_enable : pick(&plan, &d.feature_enable),
+                feature_value : pick(&plan, &d.feature_value),
+                times : times(),
+            }),
+            // Commands are events, not held values: keep only those inside the window.
+            DeviceDependentData::RS485(d) => {
+                let inside : Vec<(usize, f64)> = plan.iter().copied().filter(|&(i, t)| d.times.get(i) == Some(&t)).collect();
+                DeviceDependentData::RS485(RS485Seq {
+                    command : pick(&inside, &d.command),
+                    times : inside.iter().map(|&(_, t)| t).collect(),
+                })
+            }
+            DeviceDependentData::PulseGen(_) | DeviceDependentData::FreqFB(_) => data.clone(),
+        }
+    }
+}
+
+/// The value at `t` of the line from point `i` to the next one, or of point
+/// `i` if there is no next point or `t` is not between them.
+fn line_at(times : &[f64], values : &[f64], i : usize, t : f64) -> Option<f64> {
+    let (t_i, v_i) = (*times.get(i)?, *values.get(i)?);
+    match (times.get(i + 1), values.get(i + 1)) {
+        (Some(&t_next), Some(&v_next)) if t_i < t && t < t_next => Some(v_i + (t - t_i) / (t_next - t_i) * (v_next - v_i)),
+        _ => Some(v_i),
+    }
+}
+
+impl Sequence {
+    /// A copy of the sequence with every channel cropped to `window`.
+    pub fn cropped(&self, window : &TimeWindow) -> Sequence {
+        let mut seq = self.clone();
+        if !window.is_open() {
+            for chan in &mut seq.seq_channel {
+                chan.device_dependent = window.crop(&chan.device_dependent);
+            }
+        }
+        seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analog(data : &DeviceDependentData) -> (&[f64], &[f64]) {
+        match data {
+            DeviceDependentData::Analog(d) => (&d.times, &d.amplitude),
+            _ => panic!("not analog data"),
+        }
+    }
+
+    fn ramp() -> DeviceDependentData {
+        DeviceDependentData::Analog(AnalogSeq { amplitude : vec![0., 10., 20., 30.], times : vec![0., 1., 2., 3.] })
+    }
+
+    #[test]
+    fn crop_interpolates_lines_at_both_edges() {
+        let window = TimeWindow { t0 : Some(1.5), t1 : Some(2.5) };
+        let cropped = window.crop(&ramp());
+        assert_eq!(analog(&cropped), (&[1.5, 2., 2.5][..], &[15., 20., 25.][..]));
+    }
+
+    #[test]
+    fn crop_keeps_open_sides() {
+        let cropped = TimeWindow { t0 : None, t1 : Some(1.5) }.crop(&ramp());
+        assert_eq!(analog(&cropped), (&[0., 1., 1.5][..], &[0., 10., 15.][..]));
+        let cropped = TimeWindow { t0 : Some(2.5), t1 : None }.crop(&ramp());
+        assert_eq!(analog(&cropped), (&[2.5, 3.][..], &[25., 30.][..]));
+    }
+
+    #[test]
+    fn crop_holds_digital_levels_at_both_edges() {
+        let wave = DeviceDependentData::Digital(DigitalSeq { value : vec![false, true, false], times : vec![0., 1., 2.] });
+        let DeviceDependentData::Digital(d) = (TimeWindow { t0 : Some(0.5), t1 : Some(1.5) }).crop(&wave) else { panic!() };
+        assert_eq!((d.times, d.value), (vec![0.5, 1., 1.5], vec![false, true, true]));
+    }
+
+    #[test]
+    fn crop_holds_the_dds_feature_and_interpolates_its_values() {
+        let dds = DeviceDependentData::DDSRF(DDSSeq {
+            amplitude : vec![0., 1.], frequency : vec![80., 100.],
+            feature_enable : vec![true, false], feature_value : vec![0.5, 0.], times : vec![0., 2.],
+        });
+        let DeviceDependentData::DDSRF(d) = (TimeWindow { t0 : Some(1.), t1 : None }).crop(&dds) else { panic!() };
+        assert_eq!((d.times, d.amplitude, d.frequency), (vec![1., 2.], vec![0.5, 1.], vec![90., 100.]));
+        assert_eq!((d.feature_enable, d.feature_value), (vec![true, false], vec![0.5, 0.]));
+    }
+
+    #[test]
+    fn crop_after_the_last_point_holds_the_last_value() {
+        let cropped = TimeWindow { t0 : Some(5.), t1 : Some(6.) }.crop(&ramp());
+        assert_eq!(analog(&cropped), (&[5., 6.][..], &[30., 30.][..]));
+    }
+
+    #[test]
+    fn check_rejects_inverted_windows() {
+        assert!(TimeWindow { t0 : Some(2.), t1 : Some(1.) }.check().is_err());
+        assert!(TimeWindow { t0 : Some(1.), t1 : Some(1.) }.check().is_ok());
+        assert!(TimeWindow { t0 : Some(2.), t1 : None }.check().is_ok());
+    }
+}